@@ -0,0 +1,72 @@
+use anyhow::{Context, Result};
+use fantoccini::Client;
+use log::{info, warn};
+use std::fs;
+use std::path::Path;
+
+use crate::context::RunContext;
+
+/// Write a file, creating its parent directories if needed
+pub fn write_file(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+
+    fs::write(path, contents).with_context(|| format!("Failed to write file: {}", path.display()))
+}
+
+/// Capture the whole scrollable page by temporarily growing the window to fit it
+pub async fn full_page_screenshot(client: &Client) -> Result<Vec<u8>> {
+    let (width, height) = client.get_window_size().await?;
+
+    // Page size plus the space taken by the browser chrome around the viewport
+    let dims = client
+        .execute(
+            "const doc = document.documentElement;
+             return [
+                 Math.max(doc.scrollWidth, window.innerWidth) + window.outerWidth - window.innerWidth,
+                 Math.max(doc.scrollHeight, window.innerHeight) + window.outerHeight - window.innerHeight
+             ];",
+            vec![],
+        )
+        .await
+        .context("Failed to measure page size")?;
+    let full_width = dims[0].as_u64().unwrap_or(width);
+    let full_height = dims[1].as_u64().unwrap_or(height);
+
+    client
+        .set_window_size(full_width as u32, full_height as u32)
+        .await
+        .context("Failed to resize window for full-page screenshot")?;
+    let screenshot = client.screenshot().await;
+
+    // Always restore the original window size, even if the screenshot failed
+    client.set_window_size(width as u32, height as u32).await?;
+
+    screenshot.context("Failed to capture screenshot")
+}
+
+/// Save a screenshot and the page source of the current page into `dir`
+///
+/// Errors are logged rather than returned so they never mask the original failure.
+pub async fn capture_failure(client: &Client, dir: &Path, ctx: &RunContext) {
+    let name = ctx.render("{{domain}}-step{{step}}-{{timestamp}}");
+
+    match client.screenshot().await {
+        Ok(png) => save(&dir.join(format!("{}.png", name)), png),
+        Err(e) => warn!("Failed to capture failure screenshot: {}", e),
+    }
+
+    match client.source().await {
+        Ok(html) => save(&dir.join(format!("{}.html", name)), html),
+        Err(e) => warn!("Failed to capture failure page source: {}", e),
+    }
+}
+
+fn save(path: &Path, contents: impl AsRef<[u8]>) {
+    match write_file(path, contents) {
+        Ok(()) => info!("Saved failure artifact: {}", path.display()),
+        Err(e) => warn!("{:#}", e),
+    }
+}
@@ -0,0 +1,55 @@
+use std::fmt;
+
+/// A failed assertion instruction, with the expected and actual values
+#[derive(Debug, Clone)]
+pub struct AssertionFailure {
+    /// What was checked, e.g. "text of #status"
+    pub check: String,
+    /// Expected value
+    pub expected: String,
+    /// Actual value found on the page
+    pub actual: String,
+}
+
+impl AssertionFailure {
+    pub fn new(
+        check: impl Into<String>,
+        expected: impl Into<String>,
+        actual: impl Into<String>,
+    ) -> Self {
+        Self {
+            check: check.into(),
+            expected: expected.into(),
+            actual: actual.into(),
+        }
+    }
+}
+
+impl fmt::Display for AssertionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Assertion failed for {}: expected {}, got {}",
+            self.check, self.expected, self.actual
+        )
+    }
+}
+
+impl std::error::Error for AssertionFailure {}
+
+/// An assertion failure recorded during a run
+#[derive(Debug, Clone)]
+pub struct FailedAssertion {
+    /// URL of the link being processed
+    pub url: String,
+    /// Instruction number within the instruction file
+    pub step: usize,
+    /// The failure details
+    pub failure: AssertionFailure,
+}
+
+impl fmt::Display for FailedAssertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} step #{}] {}", self.url, self.step, self.failure)
+    }
+}
@@ -15,6 +15,10 @@ use crate::models::Instruction;
 #[derive(Debug, Deserialize)]
 pub struct ConfigYaml {
     pub domains: HashMap<String, DomainConfig>,
+
+    /// Optional default values for template variables
+    #[serde(default)]
+    pub variables: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -33,6 +37,31 @@ pub struct DomainConfig {
     /// Optional cookie file path for this domain
     #[serde(default)]
     pub cookie_file: Option<String>,
+
+    /// Optional: switch to windows opened by a click
+    #[serde(default)]
+    pub follow_new_windows: Option<bool>,
+
+    /// Optional policy for dialogs that block an instruction
+    #[serde(default)]
+    pub unexpected_dialog: Option<DialogPolicy>,
+}
+
+/// What to do with an unexpected JavaScript dialog
+///
+/// A dialog left open by the previous instruction is handled before the next one starts. A
+/// dialog that interrupts an instruction is also handled, but that instruction still fails and
+/// is not retried, since it may already have had side effects.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum DialogPolicy {
+    /// Accept the dialog; a dialog left open lets the next instruction run
+    Accept,
+    /// Dismiss the dialog; a dialog left open lets the next instruction run
+    Dismiss,
+    /// Close the dialog and fail the instruction
+    #[default]
+    Fail,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -113,10 +142,72 @@ pub fn find_instruction_file_for_domain(
 }
 
 /// Load and parse instruction file
+///
+/// Files reached through `call` instructions are loaded too, so that missing files and call
+/// cycles are reported before anything runs.
 pub fn load_instructions_file(path: &Path) -> Result<Vec<Instruction>> {
-    let contents = fs::read_to_string(path).context("Failed to read instruction file")?;
+    let instructions = parse_instructions_file(path)?;
+
+    let root = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let top_level: Vec<&Instruction> = instructions.iter().collect();
+    check_calls(&root, &top_level, &mut vec![root.clone()])?;
+
+    Ok(instructions)
+}
+
+fn parse_instructions_file(path: &Path) -> Result<Vec<Instruction>> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read instruction file: {}", path.display()))?;
 
-    serde_yaml::from_str(&contents).context("Failed to parse instruction file")
+    serde_yaml::from_str(&contents)
+        .with_context(|| format!("Failed to parse instruction file: {}", path.display()))
+}
+
+/// Follow the `call` instructions of a file, failing when one leads back to a file in `stack`
+fn check_calls(path: &Path, instructions: &[&Instruction], stack: &mut Vec<PathBuf>) -> Result<()> {
+    let base_dir = path.parent().unwrap_or_else(|| Path::new("."));
+
+    for instruction in instructions {
+        check_calls(path, &instruction.nested(), stack)?;
+
+        let Instruction::Call { file, .. } = instruction else {
+            continue;
+        };
+        // Templated paths can only be resolved, and checked, when the call runs
+        if file.contains("{{") {
+            continue;
+        }
+
+        let callee = base_dir.join(file);
+        let callee = callee.canonicalize().with_context(|| {
+            format!(
+                "Called file not found: {} (called from {})",
+                callee.display(),
+                path.display()
+            )
+        })?;
+
+        if let Some(start) = stack.iter().position(|caller| *caller == callee) {
+            let cycle = stack[start..]
+                .iter()
+                .chain([&callee])
+                .map(|file| file.display().to_string())
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            return Err(anyhow::anyhow!(
+                "Call cycle between instruction files: {}",
+                cycle
+            ));
+        }
+
+        let callee_instructions = parse_instructions_file(&callee)?;
+        let top_level: Vec<&Instruction> = callee_instructions.iter().collect();
+        stack.push(callee.clone());
+        check_calls(&callee, &top_level, stack)?;
+        stack.pop();
+    }
+
+    Ok(())
 }
 
 /// Convenience function to get domain from a URL
@@ -127,3 +218,88 @@ pub fn get_domain_from_url(url: &str) -> Result<String> {
         .map(|d| d.to_string())
         .ok_or_else(|| anyhow::anyhow!("Invalid domain"))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write instruction files to a fresh directory named after the test
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "web_automator_calls_{}_{}",
+            test,
+            std::process::id()
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        dir
+    }
+
+    fn load_error(test: &str, files: &[(&str, &str)]) -> String {
+        let dir = write_files(test, files);
+        let result = load_instructions_file(&dir.join(files[0].0));
+        fs::remove_dir_all(&dir).ok();
+        format!("{:#}", result.unwrap_err())
+    }
+
+    #[test]
+    fn direct_call_cycle_is_rejected() {
+        let error = load_error(
+            "direct",
+            &[("main.yaml", "- action: call\n  file: main.yaml\n")],
+        );
+        assert!(error.starts_with("Call cycle between instruction files:"));
+        assert_eq!(error.matches("main.yaml").count(), 2);
+    }
+
+    #[test]
+    fn indirect_call_cycle_is_rejected() {
+        let error = load_error(
+            "indirect",
+            &[
+                ("main.yaml", "- action: call\n  file: login.yaml\n"),
+                (
+                    "login.yaml",
+                    "- action: if\n  condition:\n    exists:\n      selector: '#login'\n  then:\n    - action: call\n      file: main.yaml\n",
+                ),
+            ],
+        );
+        assert!(error.starts_with("Call cycle between instruction files:"));
+        let files: Vec<&str> = error
+            .split(": ")
+            .nth(1)
+            .unwrap()
+            .split(" -> ")
+            .map(|file| file.rsplit('/').next().unwrap())
+            .collect();
+        assert_eq!(files, ["main.yaml", "login.yaml", "main.yaml"]);
+    }
+
+    #[test]
+    fn repeated_calls_without_cycle_are_accepted() {
+        let dir = write_files(
+            "repeated",
+            &[
+                (
+                    "main.yaml",
+                    "- action: call\n  file: common.yaml\n- action: call\n  file: common.yaml\n- action: call\n  file: '{{next}}.yaml'\n",
+                ),
+                ("common.yaml", "- action: refresh\n"),
+            ],
+        );
+        let result = load_instructions_file(&dir.join("main.yaml"));
+        fs::remove_dir_all(&dir).ok();
+        assert_eq!(result.unwrap().len(), 3);
+    }
+
+    #[test]
+    fn missing_called_file_is_reported() {
+        let error = load_error(
+            "missing",
+            &[("main.yaml", "- action: call\n  file: nowhere.yaml\n")],
+        );
+        assert!(error.starts_with("Called file not found:"));
+    }
+}
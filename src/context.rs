@@ -0,0 +1,249 @@
+use anyhow::{Context, Result};
+use fantoccini::wd::WindowHandle;
+use log::warn;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::assertions::FailedAssertion;
+use crate::config::DialogPolicy;
+use crate::models::Instruction;
+use crate::output_sink::OutputSink;
+
+/// Fields holding nested instructions, which are rendered only when they run
+const NESTED_STEP_FIELDS: [&str; 5] = ["then", "else", "steps", "catch", "finally"];
+
+/// State carried through a run and made available to instructions
+#[derive(Debug, Default)]
+pub struct RunContext {
+    /// URL of the link being processed
+    pub url: String,
+    /// Domain of the link being processed
+    pub domain: String,
+    /// Number of the instruction being executed
+    pub step: usize,
+    /// Directory of the instruction file, used to resolve relative paths
+    pub base_dir: PathBuf,
+    /// Values from the config, the command line, the links file and earlier instructions
+    pub variables: HashMap<String, Value>,
+    /// Windows that were open before the current instruction ran
+    pub window_handles: Vec<WindowHandle>,
+    /// Whether to switch to windows opened by a click
+    pub follow_new_windows: bool,
+    /// What to do with a dialog that blocks an instruction
+    pub unexpected_dialog: DialogPolicy,
+    /// Directory the browser saves downloads into, if configured
+    pub download_dir: Option<PathBuf>,
+    /// Downloads already returned by `wait_for_download`
+    pub claimed_downloads: HashSet<PathBuf>,
+    /// Directory to save a screenshot and the page source into when an instruction fails
+    pub failure_artifacts_dir: Option<PathBuf>,
+    /// Assertion failures recorded so far
+    pub failed_assertions: Vec<FailedAssertion>,
+    /// Whether assertion failures are returned as errors instead of being recorded
+    pub raise_assertions: bool,
+    /// Elements selected by enclosing `for_each` blocks, outermost first
+    pub scope: Vec<ElementScope>,
+    /// Instruction files currently being run by `call`, outermost first
+    pub call_stack: Vec<PathBuf>,
+    /// Where `extract` writes its values, if configured
+    pub output_sink: Option<Box<dyn OutputSink>>,
+    /// Directory `extract_table` writes its files into, if configured
+    pub output_dir: Option<PathBuf>,
+}
+
+/// The element a `for_each` block is currently running its steps for
+///
+/// Only the selector and index are kept, so the element can be found again after navigation.
+#[derive(Debug, Clone)]
+pub struct ElementScope {
+    /// Selector of the `for_each` block
+    pub selector: String,
+    /// Selector type: "css", "id", or "xpath"
+    pub by: Option<String>,
+    /// Shadow host chain of the `for_each` block
+    pub shadow: Option<Vec<String>>,
+    /// Position of the element among the matches
+    pub index: usize,
+}
+
+impl RunContext {
+    /// Resolve a path to an existing file, relative to the instruction file's directory
+    pub fn resolve_existing_path(&self, path: &str) -> Result<PathBuf> {
+        let resolved = self.resolve_path(path);
+        resolved
+            .canonicalize()
+            .with_context(|| format!("File not found: {}", resolved.display()))
+    }
+
+    /// Resolve a path relative to the instruction file's directory
+    pub fn resolve_path(&self, path: &str) -> PathBuf {
+        let path = Path::new(path);
+        if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            self.base_dir.join(path)
+        }
+    }
+
+    /// Expand `{{name}}` placeholders in a template string
+    ///
+    /// Unknown placeholders are left untouched. A literal `{{`, e.g. in a script or pattern, is
+    /// written as `\{{`.
+    pub fn render(&self, template: &str) -> String {
+        let mut rendered = String::with_capacity(template.len());
+        let mut rest = template;
+
+        while let Some(start) = rest.find("{{") {
+            if rest[..start].ends_with('\\') {
+                rendered.push_str(&rest[..start - 1]);
+                rendered.push_str("{{");
+                rest = &rest[start + 2..];
+                continue;
+            }
+            let Some(len) = rest[start + 2..].find("}}") else {
+                break;
+            };
+            let name = rest[start + 2..start + 2 + len].trim();
+
+            rendered.push_str(&rest[..start]);
+            match self.lookup(name) {
+                Some(value) => rendered.push_str(&value),
+                None => {
+                    warn!("Unknown template placeholder: {}", name);
+                    rendered.push_str(&rest[start..start + 4 + len]);
+                }
+            }
+            rest = &rest[start + 4 + len..];
+        }
+
+        rendered.push_str(rest);
+        rendered
+    }
+
+    /// Expand placeholders in every string field of an instruction
+    ///
+    /// Steps nested in a block are left alone, since earlier steps may still set their variables.
+    pub fn render_instruction(&self, instruction: &Instruction) -> Result<Instruction> {
+        let mut value = serde_json::to_value(instruction).context("Failed to read instruction")?;
+        if let Value::Object(fields) = &mut value {
+            for (name, field) in fields.iter_mut() {
+                if !NESTED_STEP_FIELDS.contains(&name.as_str()) {
+                    self.render_value(field);
+                }
+            }
+        }
+        serde_json::from_value(value).context("Failed to apply variables to instruction")
+    }
+
+    fn render_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) if s.contains("{{") => *s = self.render(s),
+            Value::Array(items) => items.iter_mut().for_each(|item| self.render_value(item)),
+            Value::Object(fields) => fields
+                .values_mut()
+                .for_each(|field| self.render_value(field)),
+            _ => {}
+        }
+    }
+
+    /// Look up a built-in placeholder, an environment variable or a stored variable
+    fn lookup(&self, name: &str) -> Option<String> {
+        if let Some(var) = name.strip_prefix("env.") {
+            return std::env::var(var).ok();
+        }
+
+        match name {
+            "url" => Some(self.url.clone()),
+            "domain" => Some(self.domain.clone()),
+            "step" => Some(self.step.to_string()),
+            "timestamp" => Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_millis())
+                    .unwrap_or_default()
+                    .to_string(),
+            ),
+            _ => self.variables.get(name).map(|value| match value {
+                Value::String(s) => s.clone(),
+                other => other.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> RunContext {
+        let mut ctx = RunContext {
+            url: "https://example.com/a".to_string(),
+            domain: "example.com".to_string(),
+            step: 3,
+            ..Default::default()
+        };
+        ctx.variables.insert("user".to_string(), "alice".into());
+        ctx.variables.insert("count".to_string(), 42.into());
+        ctx.variables.insert("flag".to_string(), true.into());
+        ctx
+    }
+
+    #[test]
+    fn render_builtin_and_stored_variables() {
+        let ctx = context();
+        assert_eq!(
+            ctx.render("{{domain}} step {{ step }} as {{user}}"),
+            "example.com step 3 as alice"
+        );
+    }
+
+    #[test]
+    fn render_non_string_variables_as_json() {
+        let ctx = context();
+        assert_eq!(ctx.render("{{count}}/{{flag}}"), "42/true");
+    }
+
+    #[test]
+    fn render_leaves_unknown_and_unclosed_placeholders() {
+        let ctx = context();
+        assert_eq!(ctx.render("{{missing}} {{user}}"), "{{missing}} alice");
+        assert_eq!(ctx.render("{{user}} {{user"), "alice {{user");
+    }
+
+    #[test]
+    fn render_escaped_braces_literally() {
+        let ctx = context();
+        assert_eq!(ctx.render(r"\{{user}} {{user}}"), "{{user}} alice");
+        assert_eq!(ctx.render(r"a\{{2}"), "a{{2}");
+    }
+
+    #[test]
+    fn render_environment_variables() {
+        std::env::set_var("WEB_AUTOMATOR_TEST_RENDER", "from-env");
+        let ctx = context();
+        assert_eq!(ctx.render("{{env.WEB_AUTOMATOR_TEST_RENDER}}"), "from-env");
+        assert_eq!(
+            ctx.render("{{env.WEB_AUTOMATOR_TEST_UNSET}}"),
+            "{{env.WEB_AUTOMATOR_TEST_UNSET}}"
+        );
+    }
+
+    #[test]
+    fn render_instruction_skips_nested_steps() {
+        let ctx = context();
+        let instruction: Instruction = serde_yaml::from_str(
+            "action: repeat\ntimes: 2\nsteps:\n  - action: set_var\n    name: who\n    value: \"{{user}}\"\n",
+        )
+        .unwrap();
+        let rendered = serde_json::to_value(ctx.render_instruction(&instruction).unwrap()).unwrap();
+        assert_eq!(rendered["steps"][0]["value"], "{{user}}");
+
+        let instruction: Instruction =
+            serde_yaml::from_str("action: navigate\nurl: \"https://{{domain}}/{{user}}\"\n")
+                .unwrap();
+        let rendered = serde_json::to_value(ctx.render_instruction(&instruction).unwrap()).unwrap();
+        assert_eq!(rendered["url"], "https://example.com/alice");
+    }
+}
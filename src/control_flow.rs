@@ -0,0 +1,680 @@
+use anyhow::{Context, Result};
+use fantoccini::error::{CmdError, ErrorStatus};
+use fantoccini::Client;
+use log::{error, info, warn};
+use rand::prelude::SliceRandom;
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use tokio::time::sleep;
+
+use crate::artifacts;
+use crate::assertions::{AssertionFailure, FailedAssertion};
+use crate::config;
+use crate::context::{ElementScope, RunContext};
+use crate::dialog_manager;
+use crate::instruction_handler::{self, find_elements, Scope};
+use crate::models::{Backoff, Condition, ErrorKind, Instruction};
+use crate::timing_utils::random_wait_time;
+
+/// Iteration limit of a `while` block without `max_iterations`
+const DEFAULT_MAX_ITERATIONS: u32 = 100;
+
+/// Raised by a `return` instruction to unwind to the enclosing `call`
+///
+/// Outside of a called file it ends the instruction file for the current link.
+#[derive(Debug, Clone)]
+pub struct Return(pub Option<Value>);
+
+impl fmt::Display for Return {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Returned from instruction file")
+    }
+}
+
+impl std::error::Error for Return {}
+
+/// Run one instruction, recording assertion failures instead of aborting
+///
+/// Any other error is returned so that it stops the enclosing block.
+pub async fn run_step(
+    client: &mut Client,
+    instruction: &Instruction,
+    ctx: &mut RunContext,
+) -> Result<()> {
+    let result = instruction_handler::handle_instruction(client, instruction, ctx).await;
+
+    match result.map_err(|e| e.downcast::<AssertionFailure>()) {
+        Ok(()) => Ok(()),
+        Err(Ok(failure)) if ctx.raise_assertions => Err(failure.into()),
+        Err(Ok(failure)) => {
+            if let Some(dir) = &ctx.failure_artifacts_dir {
+                artifacts::capture_failure(client, dir, ctx).await;
+            }
+            let failed = FailedAssertion {
+                url: ctx.url.clone(),
+                step: ctx.step,
+                failure,
+            };
+            error!("{}", failed);
+            ctx.failed_assertions.push(failed);
+            Ok(())
+        }
+        Err(Err(e)) => Err(e),
+    }
+}
+
+/// Run a list of nested instructions in order
+async fn run_steps(client: &mut Client, steps: &[Instruction], ctx: &mut RunContext) -> Result<()> {
+    for step in steps {
+        Box::pin(run_step(client, step, ctx)).await?;
+    }
+    Ok(())
+}
+
+/// Run a control-flow block and the instructions nested in it
+pub async fn run_block(
+    client: &mut Client,
+    instruction: &Instruction,
+    ctx: &mut RunContext,
+) -> Result<()> {
+    match instruction {
+        Instruction::If {
+            condition,
+            then,
+            otherwise,
+        } => {
+            let holds = check_condition(client, condition, ctx).await?;
+            info!("Condition {:?} is {}", condition, holds);
+
+            if holds {
+                run_steps(client, then, ctx).await
+            } else {
+                run_steps(client, otherwise.as_deref().unwrap_or_default(), ctx).await
+            }
+        }
+
+        Instruction::Repeat { times, steps } => {
+            for iteration in 1..=*times {
+                info!("Repeat iteration {}/{}", iteration, times);
+                run_steps(client, steps, ctx).await?;
+            }
+            Ok(())
+        }
+
+        Instruction::While {
+            condition,
+            max_iterations,
+            steps,
+        } => {
+            let max_iterations = max_iterations.unwrap_or(DEFAULT_MAX_ITERATIONS);
+            let mut iterations = 0;
+
+            while check_condition(client, condition, ctx).await? {
+                if iterations == max_iterations {
+                    warn!(
+                        "Stopping while loop after {} iterations, condition {:?} still holds",
+                        max_iterations, condition
+                    );
+                    break;
+                }
+                iterations += 1;
+                info!("While iteration {}", iterations);
+                run_steps(client, steps, ctx).await?;
+            }
+            Ok(())
+        }
+
+        Instruction::ForEach {
+            selector,
+            by,
+            shadow,
+            limit,
+            shuffle,
+            exclude_text,
+            steps,
+            ..
+        } => {
+            let scope = Scope {
+                path: &ctx.scope,
+                shadow: shadow.as_deref(),
+            };
+            let elements = find_elements(client, selector, by.as_deref(), scope).await?;
+
+            // Decide up front which elements to visit; they are found again by index later
+            let mut indices = Vec::new();
+            for (index, elem) in elements.iter().enumerate() {
+                if let (Some(exclude_texts), Ok(text)) = (exclude_text, elem.text().await) {
+                    if exclude_texts.iter().any(|exclude| text.contains(exclude)) {
+                        continue;
+                    }
+                }
+                indices.push(index);
+            }
+            if shuffle.unwrap_or(false) {
+                indices.shuffle(&mut rand::thread_rng());
+            }
+            if let Some(limit) = limit {
+                indices.truncate(*limit);
+            }
+            info!(
+                "Running steps for {} of {} elements matching {}",
+                indices.len(),
+                elements.len(),
+                selector
+            );
+
+            for (n, index) in indices.iter().enumerate() {
+                info!(
+                    "For each iteration {}/{}: {} #{}",
+                    n + 1,
+                    indices.len(),
+                    selector,
+                    index
+                );
+                ctx.scope.push(ElementScope {
+                    selector: selector.clone(),
+                    by: by.clone(),
+                    shadow: shadow.clone(),
+                    index: *index,
+                });
+                let result = run_steps(client, steps, ctx).await;
+                ctx.scope.pop();
+                result?;
+            }
+            Ok(())
+        }
+
+        Instruction::Retry {
+            attempts,
+            delay,
+            delay_stdev,
+            backoff,
+            retry_on,
+            steps,
+        } => {
+            let attempts = attempts.unwrap_or(3).max(1);
+            let retry_on = retry_on.as_deref().unwrap_or(&[ErrorKind::Any]);
+
+            // Failed assertions only count as errors here if they are worth retrying; the last
+            // one is still recorded by the enclosing step
+            let raise_assertions = ctx.raise_assertions;
+            ctx.raise_assertions |= retry_on.contains(&ErrorKind::Assertion);
+
+            let mut attempt = 1;
+            let result = loop {
+                let result = run_steps(client, steps, ctx).await;
+                let e = match result {
+                    Err(e) if attempt < attempts && is_retryable(&e, retry_on) => e,
+                    result => break result,
+                };
+
+                // Grow the delay with every failed attempt, keeping its relative spread
+                let scale = backoff_scale(backoff.unwrap_or_default(), attempt);
+                let mean = delay.unwrap_or(1.0) * scale;
+                let wait_time = match delay_stdev {
+                    Some(stdev) => match random_wait_time(mean, stdev * scale) {
+                        Ok(wait_time) => wait_time,
+                        Err(e) => break Err(e),
+                    },
+                    None => mean,
+                };
+
+                warn!(
+                    "Attempt {}/{} failed, retrying in {:.1} seconds: {:#}",
+                    attempt, attempts, wait_time, e
+                );
+                sleep(Duration::from_secs_f64(wait_time)).await;
+                attempt += 1;
+            };
+
+            ctx.raise_assertions = raise_assertions;
+            result
+        }
+
+        Instruction::Try {
+            steps,
+            catch,
+            finally,
+        } => {
+            let mut result = run_steps(client, steps, ctx).await;
+
+            // A `return` is not a failure, so it skips the catch steps
+            let caught = match &result {
+                Err(e) if !e.is::<Return>() => Some(format!("{:#}", e)),
+                _ => None,
+            };
+            if let (Some(message), Some(catch)) = (caught, catch) {
+                warn!("Caught error: {}", message);
+                ctx.variables.insert("error".to_string(), message.into());
+                result = run_steps(client, catch, ctx).await;
+            }
+
+            if let Some(finally) = finally {
+                match (&result, run_steps(client, finally, ctx).await) {
+                    (_, Ok(())) => {}
+                    (Ok(()), Err(e)) => result = Err(e),
+                    (Err(_), Err(e)) => error!("Finally steps failed as well: {:#}", e),
+                }
+            }
+            result
+        }
+
+        Instruction::Call {
+            file,
+            params,
+            store_as,
+        } => {
+            let path = ctx.resolve_existing_path(file)?;
+            if ctx.call_stack.contains(&path) {
+                return Err(anyhow::anyhow!(
+                    "Call cycle between instruction files: {} calls itself",
+                    path.display()
+                ));
+            }
+            let instructions = config::load_instructions_file(&path)?;
+            info!("Calling instruction file: {}", path.display());
+
+            // The called file sees the caller's variables plus its parameters, but its own
+            // variables, like its relative paths, stay local to it
+            let caller_variables = ctx.variables.clone();
+            ctx.variables.extend(params.clone().unwrap_or_default());
+            let caller_dir = std::mem::replace(
+                &mut ctx.base_dir,
+                path.parent().unwrap_or(Path::new(".")).to_path_buf(),
+            );
+            ctx.call_stack.push(path.clone());
+
+            let result = run_steps(client, &instructions, ctx).await;
+
+            ctx.call_stack.pop();
+            ctx.base_dir = caller_dir;
+            ctx.variables = caller_variables;
+
+            let returned = match result {
+                Ok(()) => None,
+                Err(e) => match e.downcast::<Return>() {
+                    Ok(Return(value)) => value,
+                    Err(e) => return Err(e.context(format!("In called file {}", path.display()))),
+                },
+            };
+            if let Some(name) = store_as {
+                ctx.variables
+                    .insert(name.clone(), returned.unwrap_or(Value::Null));
+            }
+            Ok(())
+        }
+
+        _ => Err(anyhow::anyhow!(
+            "Not a control-flow block: {:?}",
+            instruction
+        )),
+    }
+}
+
+/// Evaluate a condition once against the current page and variables
+pub async fn check_condition(
+    client: &Client,
+    condition: &Condition,
+    ctx: &RunContext,
+) -> Result<bool> {
+    // Look at the page as it is right now rather than waiting for elements to appear
+    let implicit = instruction_handler::set_implicit_wait(client, Some(Duration::ZERO)).await?;
+    let result = evaluate(client, condition, ctx).await;
+    instruction_handler::set_implicit_wait(client, implicit).await?;
+    result
+}
+
+async fn evaluate(client: &Client, condition: &Condition, ctx: &RunContext) -> Result<bool> {
+    // Element checks may look inside iframes; the frames are left again before combining
+    let frames = match condition {
+        Condition::Exists { frame, .. }
+        | Condition::Visible { frame, .. }
+        | Condition::Text { frame, .. } => frame.as_deref().unwrap_or_default(),
+        _ => &[],
+    };
+    let (entered, result) = instruction_handler::enter_frames(client, frames).await;
+    let result = match result {
+        Ok(()) => evaluate_here(client, condition, ctx).await,
+        Err(e) => Err(e),
+    };
+    instruction_handler::leave_frames(client, entered, result).await
+}
+
+/// Evaluate a condition in the current frame
+async fn evaluate_here(client: &Client, condition: &Condition, ctx: &RunContext) -> Result<bool> {
+    match condition {
+        Condition::Exists {
+            selector,
+            by,
+            shadow,
+            ..
+        } => Ok(
+            !find_elements(client, selector, by.as_deref(), scoped(ctx, shadow))
+                .await?
+                .is_empty(),
+        ),
+
+        Condition::Visible {
+            selector,
+            by,
+            shadow,
+            ..
+        } => {
+            let elements =
+                find_elements(client, selector, by.as_deref(), scoped(ctx, shadow)).await?;
+            for elem in elements {
+                if elem.is_displayed().await.unwrap_or(false) {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+
+        Condition::Text {
+            selector,
+            by,
+            shadow,
+            pattern,
+            ..
+        } => {
+            let regex = compile(pattern)?;
+            let elements =
+                find_elements(client, selector, by.as_deref(), scoped(ctx, shadow)).await?;
+            match elements.first() {
+                Some(elem) => Ok(regex.is_match(&elem.text().await?)),
+                None => Ok(false),
+            }
+        }
+
+        Condition::Url(pattern) => {
+            Ok(compile(pattern)?.is_match(client.current_url().await?.as_str()))
+        }
+
+        Condition::Title(pattern) => Ok(compile(pattern)?.is_match(&client.title().await?)),
+
+        Condition::Var {
+            name,
+            equals,
+            not_equals,
+            matches,
+            greater_than,
+            less_than,
+        } => {
+            let Some(value) = ctx.variables.get(name) else {
+                return Ok(false);
+            };
+
+            let no_comparison = equals.is_none()
+                && not_equals.is_none()
+                && matches.is_none()
+                && greater_than.is_none()
+                && less_than.is_none();
+            if no_comparison {
+                return Ok(is_truthy(value));
+            }
+
+            let matches = match matches {
+                Some(pattern) => compile(pattern)?.is_match(&value_to_string(value)),
+                None => true,
+            };
+            let number = as_number(value);
+            Ok(matches
+                && equals
+                    .as_ref()
+                    .is_none_or(|expected| loosely_equal(value, expected))
+                && not_equals
+                    .as_ref()
+                    .is_none_or(|other| !loosely_equal(value, other))
+                && greater_than.is_none_or(|min| number.is_some_and(|n| n > min))
+                && less_than.is_none_or(|max| number.is_some_and(|n| n < max)))
+        }
+
+        Condition::Not(inner) => Ok(!Box::pin(evaluate(client, inner, ctx)).await?),
+
+        Condition::All(conditions) => {
+            for inner in conditions {
+                if !Box::pin(evaluate(client, inner, ctx)).await? {
+                    return Ok(false);
+                }
+            }
+            Ok(true)
+        }
+
+        Condition::Any(conditions) => {
+            for inner in conditions {
+                if Box::pin(evaluate(client, inner, ctx)).await? {
+                    return Ok(true);
+                }
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Search within the current `for_each` element and the given shadow host chain
+fn scoped<'a>(ctx: &'a RunContext, shadow: &'a Option<Vec<String>>) -> Scope<'a> {
+    Scope {
+        path: &ctx.scope,
+        shadow: shadow.as_deref(),
+    }
+}
+
+/// Factor applied to a `retry` block's delay after the given failed attempt, counting from 1
+fn backoff_scale(backoff: Backoff, attempt: u32) -> f64 {
+    match backoff {
+        Backoff::Constant => 1.0,
+        Backoff::Linear => attempt as f64,
+        Backoff::Exponential => 2f64.powi(attempt as i32 - 1),
+    }
+}
+
+/// Whether an error is of one of the kinds a `retry` block retries on
+fn is_retryable(e: &anyhow::Error, retry_on: &[ErrorKind]) -> bool {
+    if e.is::<Return>() {
+        return false;
+    }
+
+    let status = e
+        .chain()
+        .find_map(|cause| match cause.downcast_ref::<CmdError>() {
+            Some(CmdError::Standard(e)) => Some(&e.error),
+            _ => None,
+        });
+    let message = format!("{:#}", e);
+
+    retry_on.iter().any(|kind| match kind {
+        ErrorKind::Any => true,
+        ErrorKind::Timeout => {
+            matches!(
+                status,
+                Some(ErrorStatus::Timeout | ErrorStatus::ScriptTimeout)
+            ) || e
+                .chain()
+                .any(|cause| cause.is::<tokio::time::error::Elapsed>())
+                || message.contains("Timeout")
+                || message.contains("Timed out")
+        }
+        ErrorKind::NotFound => {
+            status == Some(&ErrorStatus::NoSuchElement)
+                || message.contains("Failed to find element")
+                || message.contains("is no longer on the page")
+        }
+        ErrorKind::Stale => status == Some(&ErrorStatus::StaleElementReference),
+        ErrorKind::Intercepted => {
+            status == Some(&ErrorStatus::ElementClickIntercepted)
+                || message.contains("was intercepted by")
+        }
+        ErrorKind::NotInteractable => status == Some(&ErrorStatus::ElementNotInteractable),
+        ErrorKind::Script => status == Some(&ErrorStatus::JavascriptError),
+        ErrorKind::Dialog => {
+            dialog_manager::is_blocked_by_dialog(e) || message.contains("Unexpected dialog")
+        }
+        ErrorKind::Assertion => e.is::<AssertionFailure>(),
+    })
+}
+
+fn compile(pattern: &str) -> Result<Regex> {
+    Regex::new(pattern).with_context(|| format!("Invalid condition pattern: {}", pattern))
+}
+
+/// Strings are compared raw, everything else by its JSON representation
+fn value_to_string(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Numbers, and strings that parse as numbers
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Compare numerically when both sides are numbers, so that `3` equals `3.0` and `"3"`
+fn loosely_equal(value: &Value, expected: &Value) -> bool {
+    match (as_number(value), as_number(expected)) {
+        (Some(a), Some(b)) => a == b,
+        _ => value_to_string(value) == value_to_string(expected),
+    }
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        Value::Array(items) => !items.is_empty(),
+        Value::Object(_) => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use fantoccini::error::WebDriver;
+    use serde_json::json;
+
+    fn webdriver_error(status: ErrorStatus, message: &str) -> anyhow::Error {
+        let e = CmdError::Standard(WebDriver::new(status, message.to_string()));
+        anyhow::Error::new(e).context("Failed to click element")
+    }
+
+    fn retryable_kinds(e: &anyhow::Error) -> Vec<ErrorKind> {
+        [
+            ErrorKind::Timeout,
+            ErrorKind::NotFound,
+            ErrorKind::Stale,
+            ErrorKind::Intercepted,
+            ErrorKind::NotInteractable,
+            ErrorKind::Script,
+            ErrorKind::Dialog,
+            ErrorKind::Assertion,
+        ]
+        .into_iter()
+        .filter(|kind| is_retryable(e, &[*kind]))
+        .collect()
+    }
+
+    #[test]
+    fn is_retryable_matches_webdriver_status() {
+        let cases = [
+            (ErrorStatus::Timeout, ErrorKind::Timeout),
+            (ErrorStatus::ScriptTimeout, ErrorKind::Timeout),
+            (ErrorStatus::NoSuchElement, ErrorKind::NotFound),
+            (ErrorStatus::StaleElementReference, ErrorKind::Stale),
+            (ErrorStatus::ElementClickIntercepted, ErrorKind::Intercepted),
+            (
+                ErrorStatus::ElementNotInteractable,
+                ErrorKind::NotInteractable,
+            ),
+            (ErrorStatus::JavascriptError, ErrorKind::Script),
+            (ErrorStatus::UnexpectedAlertOpen, ErrorKind::Dialog),
+        ];
+        for (status, kind) in cases {
+            let message = format!("{:?}", status);
+            let e = webdriver_error(status, "error");
+            assert_eq!(retryable_kinds(&e), [kind], "{}", message);
+        }
+    }
+
+    #[test]
+    fn is_retryable_matches_messages_and_error_types() {
+        let e = anyhow::anyhow!("Timed out waiting for element");
+        assert_eq!(retryable_kinds(&e), [ErrorKind::Timeout]);
+        let e = anyhow::anyhow!("Failed to find element: #missing");
+        assert_eq!(retryable_kinds(&e), [ErrorKind::NotFound]);
+        let e = anyhow::anyhow!("Other element was intercepted by an overlay");
+        assert_eq!(retryable_kinds(&e), [ErrorKind::Intercepted]);
+
+        let e = anyhow::Error::new(AssertionFailure::new("text of #status", "Done", "Busy"));
+        assert_eq!(retryable_kinds(&e), [ErrorKind::Assertion]);
+        assert!(is_retryable(&e, &[ErrorKind::Any]));
+    }
+
+    #[test]
+    fn is_retryable_never_retries_return() {
+        let e = anyhow::Error::new(Return(None));
+        assert!(!is_retryable(&e, &[ErrorKind::Any]));
+    }
+
+    #[test]
+    fn backoff_scale_grows_per_attempt() {
+        let scales = |backoff| {
+            (1..=4)
+                .map(|n| backoff_scale(backoff, n))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(scales(Backoff::Constant), [1.0, 1.0, 1.0, 1.0]);
+        assert_eq!(scales(Backoff::Linear), [1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(scales(Backoff::Exponential), [1.0, 2.0, 4.0, 8.0]);
+    }
+
+    #[test]
+    fn loosely_equal_compares_numbers_numerically() {
+        assert!(loosely_equal(&json!(3), &json!(3.0)));
+        assert!(loosely_equal(&json!("3"), &json!(3)));
+        assert!(loosely_equal(&json!(" 2.5 "), &json!("2.50")));
+        assert!(!loosely_equal(&json!(3), &json!("three")));
+    }
+
+    #[test]
+    fn loosely_equal_compares_other_values_as_strings() {
+        assert!(loosely_equal(&json!("done"), &json!("done")));
+        assert!(!loosely_equal(&json!("Done"), &json!("done")));
+        assert!(loosely_equal(&json!(true), &json!("true")));
+        assert!(loosely_equal(&json!(null), &json!("null")));
+        assert!(loosely_equal(&json!(["a"]), &json!(["a"])));
+    }
+
+    #[test]
+    fn is_truthy_follows_value_kind() {
+        for value in [
+            json!(null),
+            json!(false),
+            json!(0),
+            json!(0.0),
+            json!(""),
+            json!([]),
+        ] {
+            assert!(!is_truthy(&value), "{} should be falsy", value);
+        }
+        for value in [
+            json!(true),
+            json!(-1),
+            json!(0.5),
+            json!("0"),
+            json!([0]),
+            json!({}),
+        ] {
+            assert!(is_truthy(&value), "{} should be truthy", value);
+        }
+    }
+}
@@ -4,8 +4,11 @@ use fantoccini::Client;
 use log::{info, warn};
 use std::fs::File;
 use std::io::BufReader;
+use std::path::Path;
+use time::OffsetDateTime;
 use url::Url;
 
+use crate::artifacts;
 use crate::models::Cookie;
 
 pub async fn load_cookies_for_domain(
@@ -30,7 +33,7 @@ pub async fn load_cookies_for_domain(
     let domain_cookies: Vec<Cookie> = cookies
         .into_iter()
         .filter(|cookie| {
-            cookie.domain.as_ref().map_or(false, |cookie_domain| {
+            cookie.domain.as_ref().is_some_and(|cookie_domain| {
                 target_domain.ends_with(cookie_domain) || cookie_domain.ends_with(target_domain)
             })
         })
@@ -43,29 +46,73 @@ pub async fn load_cookies_for_domain(
 
     let mut cookies_added = false;
     for cookie in domain_cookies {
-        // Create a Fantoccini cookie with all available properties
-        let mut fantoccini_cookie =
-            FantocciniCookie::build((cookie.name.clone(), cookie.value.clone()));
+        client.add_cookie(to_webdriver_cookie(cookie)).await?;
+        cookies_added = true;
+    }
 
-        // Set optional properties if available
-        if let Some(domain) = cookie.domain {
-            fantoccini_cookie = fantoccini_cookie.domain(domain);
-        }
-        if let Some(path) = cookie.path {
-            fantoccini_cookie = fantoccini_cookie.path(path);
-        }
-        if let Some(secure) = cookie.secure {
-            fantoccini_cookie = fantoccini_cookie.secure(secure);
-        }
-        if let Some(http_only) = cookie.http_only {
-            fantoccini_cookie = fantoccini_cookie.http_only(http_only);
+    Ok(cookies_added)
+}
+
+/// Convert a cookie from the cookie file format to a WebDriver cookie
+pub fn to_webdriver_cookie(cookie: Cookie) -> FantocciniCookie<'static> {
+    // Create a Fantoccini cookie with all available properties
+    let mut fantoccini_cookie = FantocciniCookie::build((cookie.name, cookie.value));
+
+    // Set optional properties if available
+    if let Some(domain) = cookie.domain {
+        fantoccini_cookie = fantoccini_cookie.domain(domain);
+    }
+    if let Some(path) = cookie.path {
+        fantoccini_cookie = fantoccini_cookie.path(path);
+    }
+    if let Some(secure) = cookie.secure {
+        fantoccini_cookie = fantoccini_cookie.secure(secure);
+    }
+    if let Some(http_only) = cookie.http_only {
+        fantoccini_cookie = fantoccini_cookie.http_only(http_only);
+    }
+    if let Some(expiry) = cookie.expiry {
+        match i64::try_from(expiry)
+            .ok()
+            .and_then(|expiry| OffsetDateTime::from_unix_timestamp(expiry).ok())
+        {
+            Some(expires) => fantoccini_cookie = fantoccini_cookie.expires(expires),
+            None => warn!("Ignoring invalid expiry {} of cookie", expiry),
         }
+    }
 
-        client.add_cookie(fantoccini_cookie.into()).await?;
-        cookies_added = true;
+    fantoccini_cookie.into()
+}
+
+/// Convert a WebDriver cookie to the cookie file format
+pub fn from_webdriver_cookie(cookie: &FantocciniCookie) -> Cookie {
+    Cookie {
+        name: cookie.name().to_string(),
+        value: cookie.value().to_string(),
+        domain: cookie.domain().map(str::to_string),
+        path: cookie.path().map(str::to_string),
+        secure: cookie.secure(),
+        http_only: cookie.http_only(),
+        expiry: cookie
+            .expires_datetime()
+            .and_then(|expires| u64::try_from(expires.unix_timestamp()).ok()),
     }
+}
 
-    Ok(cookies_added)
+/// Save all cookies visible to the current page to a JSON cookie file
+pub async fn save_cookies(client: &Client, path: &Path) -> Result<usize> {
+    let cookies: Vec<Cookie> = client
+        .get_all_cookies()
+        .await
+        .context("Failed to read cookies")?
+        .iter()
+        .map(from_webdriver_cookie)
+        .collect();
+
+    let json = serde_json::to_string_pretty(&cookies)?;
+    artifacts::write_file(path, json)?;
+
+    Ok(cookies.len())
 }
 
 pub async fn check_domain_cookies(
@@ -87,7 +134,7 @@ pub async fn check_domain_cookies(
     let expected_cookies: Vec<Cookie> = cookies
         .into_iter()
         .filter(|cookie| {
-            cookie.domain.as_ref().map_or(false, |cookie_domain| {
+            cookie.domain.as_ref().is_some_and(|cookie_domain| {
                 domain.ends_with(cookie_domain) || cookie_domain.ends_with(domain)
             })
         })
@@ -107,7 +154,7 @@ pub async fn check_domain_cookies(
         let found = current_cookies.iter().any(|current| {
             // Match by name and domain
             let name_matches = current.name() == expected.name;
-            let domain_matches = current.domain().map_or(false, |current_domain| {
+            let domain_matches = current.domain().is_some_and(|current_domain| {
                 domain.ends_with(current_domain) || current_domain.ends_with(domain)
             });
 
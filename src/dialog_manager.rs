@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use fantoccini::error::CmdError;
+use fantoccini::Client;
+use log::warn;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+use crate::config::DialogPolicy;
+
+/// Wait until a JavaScript dialog is open and return its message
+pub async fn wait_for_dialog(client: &Client, timeout: Duration) -> Result<String> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        match client.get_alert_text().await {
+            Ok(text) => return Ok(text),
+            Err(e) if e.is_no_such_alert() && Instant::now() < deadline => {
+                sleep(Duration::from_millis(250)).await;
+            }
+            Err(e) if e.is_no_such_alert() => {
+                return Err(anyhow::anyhow!(
+                    "Timed out after {:?} waiting for a dialog",
+                    timeout
+                ))
+            }
+            Err(e) => return Err(e).context("Failed to read dialog text"),
+        }
+    }
+}
+
+/// Whether an error was caused by a JavaScript dialog blocking the command
+pub fn is_blocked_by_dialog(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| {
+        cause
+            .downcast_ref::<CmdError>()
+            .is_some_and(|e| e.is_unexpected_alert_open())
+    })
+}
+
+/// Apply `policy` to a dialog that is already open, e.g. one opened by the previous instruction
+pub async fn handle_open_dialog(client: &Client, policy: DialogPolicy) -> Result<()> {
+    if client.get_alert_text().await.is_ok() {
+        apply_policy(client, policy).await?;
+    }
+    Ok(())
+}
+
+/// Deal with a dialog that blocked an instruction according to `policy`
+///
+/// Returns an error naming the dialog when the policy is to fail.
+pub async fn apply_policy(client: &Client, policy: DialogPolicy) -> Result<()> {
+    // The browser may already have closed the dialog on its own
+    let text = client.get_alert_text().await.ok();
+    let message = text.as_deref().unwrap_or("<already closed>");
+
+    match policy {
+        DialogPolicy::Accept => {
+            warn!("Accepting unexpected dialog: {:?}", message);
+            if text.is_some() {
+                client
+                    .accept_alert()
+                    .await
+                    .context("Failed to accept dialog")?;
+            }
+            Ok(())
+        }
+        DialogPolicy::Dismiss => {
+            warn!("Dismissing unexpected dialog: {:?}", message);
+            if text.is_some() {
+                client
+                    .dismiss_alert()
+                    .await
+                    .context("Failed to dismiss dialog")?;
+            }
+            Ok(())
+        }
+        DialogPolicy::Fail => {
+            // Close it anyway so that failure artifacts can still be captured
+            if text.is_some() {
+                client.dismiss_alert().await.ok();
+            }
+            Err(anyhow::anyhow!("Unexpected dialog: {:?}", message))
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use log::info;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::{sleep, Instant};
+
+/// Suffixes browsers use for downloads that are still in progress
+const PARTIAL_SUFFIXES: [&str; 3] = [".part", ".crdownload", ".tmp"];
+
+/// Create a fresh, absolute download directory for this run under `base`
+pub fn create_run_dir(base: &Path) -> Result<PathBuf> {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or_default();
+    let dir = base.join(format!("run-{}", millis));
+
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create download directory: {}", dir.display()))?;
+
+    // Browsers need an absolute path in their download preferences
+    dir.canonicalize()
+        .with_context(|| format!("Failed to resolve download directory: {}", dir.display()))
+}
+
+/// Wait for a finished download in `dir` that has not been claimed before
+///
+/// A download is finished once it has no partial companion file and its size has stopped
+/// changing between two polls.
+pub async fn wait_for_download(
+    dir: &Path,
+    pattern: Option<&Regex>,
+    min_size: u64,
+    claimed: &HashSet<PathBuf>,
+    timeout: Duration,
+) -> Result<PathBuf> {
+    let deadline = Instant::now() + timeout;
+    let mut last_sizes: Vec<(PathBuf, u64)> = Vec::new();
+
+    loop {
+        let candidates = candidates(dir, pattern, claimed)?;
+
+        let finished = candidates
+            .iter()
+            .find(|(path, size)| *size >= min_size && last_sizes.contains(&(path.clone(), *size)));
+        if let Some((path, size)) = finished {
+            info!("Download finished: {} ({} bytes)", path.display(), size);
+            return Ok(path.clone());
+        }
+
+        if Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "Timed out after {:?} waiting for a download matching /{}/ of at least {} bytes in {}",
+                timeout,
+                pattern.map_or(".*", |re| re.as_str()),
+                min_size,
+                dir.display()
+            ));
+        }
+
+        last_sizes = candidates;
+        sleep(Duration::from_millis(500)).await;
+    }
+}
+
+/// Unclaimed, complete-looking files in `dir` whose names match `pattern`, with their sizes
+fn candidates(
+    dir: &Path,
+    pattern: Option<&Regex>,
+    claimed: &HashSet<PathBuf>,
+) -> Result<Vec<(PathBuf, u64)>> {
+    let entries = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read download directory: {}", dir.display()))?;
+
+    let mut files = Vec::new();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+
+        let partial = PARTIAL_SUFFIXES.iter().any(|suffix| {
+            name.ends_with(suffix) || dir.join(format!("{}{}", name, suffix)).exists()
+        });
+        let matches = pattern.is_none_or(|re| re.is_match(&name));
+        if partial || !matches || claimed.contains(&path) {
+            continue;
+        }
+
+        match entry.metadata() {
+            Ok(metadata) if metadata.is_file() => files.push((path, metadata.len())),
+            _ => {}
+        }
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result};
+use fantoccini::elements::Element;
+use fantoccini::Client;
+use regex::Regex;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::output_sink::csv_field;
+
+/// Lays the rows out on a grid, repeating spanned cells in every row and column they cover
+const READ_TABLE_SCRIPT: &str = r#"
+    const [root, rowSelector, cellSelector, headerRows] = arguments;
+    const rows = rowSelector
+        ? Array.from(root.querySelectorAll(rowSelector))
+        : Array.from(root.rows || []);
+    const cellsOf = row => cellSelector
+        ? Array.from(row.querySelectorAll(cellSelector))
+        : Array.from(row.cells || row.children);
+    const span = (cell, name) =>
+        Math.max(1, parseInt(cell.getAttribute(name) || cell.getAttribute('aria-' + name), 10) || 1);
+    const isHeader = cell => cell.tagName === 'TH' || cell.getAttribute('role') === 'columnheader';
+
+    const grid = rows.map(() => []);
+    let headers = 0;
+    let inHeader = true;
+    rows.forEach((row, r) => {
+        const cells = cellsOf(row);
+        if (inHeader) {
+            inHeader = (row.parentElement && row.parentElement.tagName === 'THEAD')
+                || (cells.length > 0 && cells.every(isHeader));
+            if (inHeader) headers = r + 1;
+        }
+
+        let c = 0;
+        for (const cell of cells) {
+            while (grid[r][c] !== undefined) c++;
+            const text = (cell.innerText || cell.textContent || '').trim();
+            const rowspan = Math.min(span(cell, 'rowspan'), rows.length - r);
+            const colspan = span(cell, 'colspan');
+            for (let i = 0; i < rowspan; i++) {
+                for (let j = 0; j < colspan; j++) grid[r + i][c + j] = text;
+            }
+            c += colspan;
+        }
+    });
+
+    const width = Math.max(0, ...grid.map(row => row.length));
+    return {
+        header_rows: headerRows === null ? headers : Math.min(headerRows, grid.length),
+        rows: grid.map(row => Array.from({ length: width }, (_, i) => row[i] ?? '')),
+    };
+"#;
+
+/// What an `extract` instruction reads from each element
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source<'a> {
+    /// The rendered text
+    Text,
+    /// An HTML attribute
+    Attribute(&'a str),
+    /// A DOM property, e.g. `value` of an input
+    Property(&'a str),
+    /// The inner HTML
+    InnerHtml,
+}
+
+impl<'a> Source<'a> {
+    /// Pick the source from the instruction's options, of which at most one may be set
+    pub fn new(attribute: Option<&'a str>, property: Option<&'a str>, html: bool) -> Result<Self> {
+        match (attribute, property, html) {
+            (None, None, false) => Ok(Source::Text),
+            (Some(attribute), None, false) => Ok(Source::Attribute(attribute)),
+            (None, Some(property), false) => Ok(Source::Property(property)),
+            (None, None, true) => Ok(Source::InnerHtml),
+            _ => Err(anyhow::anyhow!(
+                "extract takes at most one of 'attribute', 'property' and 'html'"
+            )),
+        }
+    }
+
+    /// Read the value from an element; missing attributes and properties give `None`
+    pub async fn read(self, elem: &Element) -> Result<Option<String>> {
+        let value = match self {
+            Source::Text => elem.text().await.map(Some),
+            Source::Attribute(name) => elem.attr(name).await,
+            Source::Property(name) => elem.prop(name).await,
+            Source::InnerHtml => elem.html(true).await.map(Some),
+        };
+        value.with_context(|| format!("Failed to read {} of element", self))
+    }
+}
+
+impl fmt::Display for Source<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Text => write!(f, "text"),
+            Source::Attribute(name) => write!(f, "attribute {}", name),
+            Source::Property(name) => write!(f, "property {}", name),
+            Source::InnerHtml => write!(f, "inner HTML"),
+        }
+    }
+}
+
+/// Apply a pattern to an extracted value, keeping the first capture group or the whole match
+pub fn capture(pattern: &Regex, value: &str) -> Option<String> {
+    let captures = pattern.captures(value)?;
+    captures
+        .get(1)
+        .or_else(|| captures.get(0))
+        .map(|m| m.as_str().to_string())
+}
+
+/// A table read by `extract_table`, with one header per column
+#[derive(Debug, Clone)]
+pub struct Table {
+    /// Unique column names
+    pub headers: Vec<String>,
+    /// Body rows, each as wide as `headers`
+    pub rows: Vec<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RawTable {
+    header_rows: usize,
+    rows: Vec<Vec<String>>,
+}
+
+/// Read a `<table>`, or the rows and cells of a grid, below `root`
+///
+/// Header rows are counted from `<thead>` and rows of header cells unless `header_rows` is
+/// given. Their texts are joined with " / " per column; empty body rows are skipped.
+pub async fn read_table(
+    client: &Client,
+    root: &Element,
+    row_selector: Option<&str>,
+    cell_selector: Option<&str>,
+    header_rows: Option<usize>,
+) -> Result<Table> {
+    let raw = client
+        .execute(
+            READ_TABLE_SCRIPT,
+            vec![
+                serde_json::to_value(root)?,
+                row_selector.into(),
+                cell_selector.into(),
+                header_rows.into(),
+            ],
+        )
+        .await
+        .context("Failed to read table")?;
+    let raw: RawTable = serde_json::from_value(raw).context("Failed to read table")?;
+
+    Ok(Table::from_raw(raw))
+}
+
+impl Table {
+    /// Name the columns after the header rows and drop empty body rows
+    fn from_raw(raw: RawTable) -> Self {
+        let header_rows = raw.header_rows.min(raw.rows.len());
+        let (header, body) = raw.rows.split_at(header_rows);
+        let width = raw.rows.first().map_or(0, Vec::len);
+
+        let mut seen = HashSet::new();
+        let headers = (0..width)
+            .map(|column| {
+                let mut parts: Vec<&str> = Vec::new();
+                for row in header {
+                    let text = row[column].as_str();
+                    // Cells spanning several header rows would otherwise repeat
+                    if !text.is_empty() && parts.last() != Some(&text) {
+                        parts.push(text);
+                    }
+                }
+                let name = match parts.join(" / ") {
+                    name if name.is_empty() => format!("column_{}", column + 1),
+                    name => name,
+                };
+                unique_name(name, &mut seen)
+            })
+            .collect();
+
+        let rows = body
+            .iter()
+            .filter(|row| row.iter().any(|cell| !cell.is_empty()))
+            .cloned()
+            .collect();
+
+        Table { headers, rows }
+    }
+
+    /// The table as CSV, starting with the header row
+    pub fn to_csv(&self) -> String {
+        std::iter::once(&self.headers)
+            .chain(&self.rows)
+            .map(|row| {
+                let fields: Vec<String> = row.iter().map(|field| csv_field(field)).collect();
+                format!("{}\n", fields.join(","))
+            })
+            .collect()
+    }
+
+    /// The table as a JSON list of objects, keeping the column order
+    pub fn to_json(&self) -> Result<String> {
+        let records: Vec<Record> = self.records().collect();
+        Ok(serde_json::to_string_pretty(&records)?)
+    }
+
+    /// The table as a list of objects, for storing in a variable
+    pub fn to_value(&self) -> Value {
+        Value::Array(
+            self.records()
+                .map(|record| {
+                    let fields = record
+                        .headers
+                        .iter()
+                        .cloned()
+                        .zip(record.cells.iter().map(|cell| Value::String(cell.clone())));
+                    Value::Object(fields.collect())
+                })
+                .collect(),
+        )
+    }
+
+    fn records(&self) -> impl Iterator<Item = Record<'_>> {
+        self.rows.iter().map(|cells| Record {
+            headers: &self.headers,
+            cells,
+        })
+    }
+}
+
+/// Add a numeric suffix to a column name that is already taken
+fn unique_name(name: String, seen: &mut HashSet<String>) -> String {
+    let mut candidate = name.clone();
+    let mut n = 2;
+    while !seen.insert(candidate.clone()) {
+        candidate = format!("{}_{}", name, n);
+        n += 1;
+    }
+    candidate
+}
+
+/// A body row serialized as an object keyed by column header, in column order
+struct Record<'a> {
+    headers: &'a [String],
+    cells: &'a [String],
+}
+
+impl Serialize for Record<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.headers.len()))?;
+        for (header, cell) in self.headers.iter().zip(self.cells) {
+            map.serialize_entry(header, cell)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_keeps_first_group() {
+        let pattern = Regex::new(r"#(\d+)").unwrap();
+        assert_eq!(
+            capture(&pattern, "Order #1234 placed"),
+            Some("1234".to_string())
+        );
+    }
+
+    #[test]
+    fn capture_falls_back_to_whole_match() {
+        let pattern = Regex::new(r"\d+").unwrap();
+        assert_eq!(
+            capture(&pattern, "Order #1234 placed"),
+            Some("1234".to_string())
+        );
+        let pattern = Regex::new(r"[A-Z]\w+").unwrap();
+        assert_eq!(
+            capture(&pattern, "order Shipped"),
+            Some("Shipped".to_string())
+        );
+    }
+
+    #[test]
+    fn capture_without_match_is_none() {
+        let pattern = Regex::new(r"#(\d+)").unwrap();
+        assert_eq!(capture(&pattern, "no order"), None);
+    }
+
+    fn raw(header_rows: usize, rows: &[&[&str]]) -> RawTable {
+        RawTable {
+            header_rows,
+            rows: rows
+                .iter()
+                .map(|row| row.iter().map(|cell| cell.to_string()).collect())
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn table_merges_header_rows_without_repeating_spans() {
+        // "Name" spans both header rows, "Score" spans both score columns
+        let table = Table::from_raw(raw(
+            2,
+            &[
+                &["Name", "Score", "Score"],
+                &["Name", "Q1", "Q2"],
+                &["Ann", "1", "2"],
+            ],
+        ));
+        assert_eq!(table.headers, ["Name", "Score / Q1", "Score / Q2"]);
+        assert_eq!(table.rows, [["Ann", "1", "2"]]);
+    }
+
+    #[test]
+    fn table_names_empty_and_duplicate_headers() {
+        let table = Table::from_raw(raw(1, &[&["Id", "", "Id", "Id"], &["1", "a", "b", "c"]]));
+        assert_eq!(table.headers, ["Id", "column_2", "Id_2", "Id_3"]);
+    }
+
+    #[test]
+    fn table_without_header_rows_uses_column_numbers() {
+        let table = Table::from_raw(raw(0, &[&["a", "b"]]));
+        assert_eq!(table.headers, ["column_1", "column_2"]);
+        assert_eq!(table.rows.len(), 1);
+    }
+
+    #[test]
+    fn table_skips_empty_body_rows() {
+        let table = Table::from_raw(raw(1, &[&["A", "B"], &["", ""], &["1", ""], &["", ""]]));
+        assert_eq!(table.rows, [["1", ""]]);
+    }
+
+    #[test]
+    fn table_clamps_header_rows_to_table_size() {
+        let table = Table::from_raw(raw(5, &[&["A"]]));
+        assert_eq!(table.headers, ["A"]);
+        assert!(table.rows.is_empty());
+    }
+
+    #[test]
+    fn table_serializes_in_column_order() {
+        let table = Table::from_raw(raw(1, &[&["b", "a"], &["x,y", "\"q\""]]));
+        assert_eq!(table.to_csv(), "b,a\n\"x,y\",\"\"\"q\"\"\"\n");
+
+        let json = table.to_json().unwrap();
+        assert!(json.find("\"b\"").unwrap() < json.find("\"a\"").unwrap());
+        assert_eq!(
+            serde_json::from_str::<Value>(&json).unwrap(),
+            serde_json::json!([{ "b": "x,y", "a": "\"q\"" }])
+        );
+        assert_eq!(
+            table.to_value(),
+            serde_json::json!([{ "b": "x,y", "a": "\"q\"" }])
+        );
+    }
+
+    #[test]
+    fn source_rejects_several_options() {
+        assert_eq!(Source::new(None, None, false).unwrap(), Source::Text);
+        assert_eq!(
+            Source::new(Some("href"), None, false).unwrap(),
+            Source::Attribute("href")
+        );
+        assert!(Source::new(Some("href"), Some("value"), false).is_err());
+        assert!(Source::new(Some("href"), None, true).is_err());
+    }
+}
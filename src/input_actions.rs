@@ -0,0 +1,238 @@
+use anyhow::{Context, Result};
+use fantoccini::actions::{
+    Actions, InputSource, KeyAction, KeyActions, MouseActions, PointerAction, MOUSE_BUTTON_LEFT,
+};
+use fantoccini::elements::Element;
+use fantoccini::key::Key;
+use fantoccini::Client;
+use std::time::Duration;
+
+/// Parse a modifier key name such as "ctrl", "shift", "alt" or "meta"
+pub fn parse_modifier(name: &str) -> Result<Key> {
+    match name.to_lowercase().as_str() {
+        "ctrl" | "control" => Ok(Key::Control),
+        "shift" => Ok(Key::Shift),
+        "alt" | "option" => Ok(Key::Alt),
+        "meta" | "cmd" | "command" => Ok(Key::Meta),
+        other => Err(anyhow::anyhow!("Unknown modifier key: {}", other)),
+    }
+}
+
+/// Parse an optional list of modifier key names
+pub fn parse_modifiers(names: Option<&[String]>) -> Result<Vec<Key>> {
+    names
+        .unwrap_or_default()
+        .iter()
+        .map(|name| parse_modifier(name))
+        .collect()
+}
+
+/// Parse a key name such as "Escape", "PageDown", "F5" or a single character
+pub fn parse_key(name: &str) -> Result<char> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(c);
+    }
+
+    let key = match name.to_lowercase().as_str() {
+        "escape" | "esc" => Key::Escape,
+        "tab" => Key::Tab,
+        "enter" => Key::Enter,
+        "return" => Key::Return,
+        "backspace" => Key::Backspace,
+        "delete" | "del" => Key::Delete,
+        "insert" => Key::Insert,
+        "space" => Key::Space,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "up" | "arrowup" => Key::Up,
+        "down" | "arrowdown" => Key::Down,
+        "left" | "arrowleft" => Key::Left,
+        "right" | "arrowright" => Key::Right,
+        "f1" => Key::F1,
+        "f2" => Key::F2,
+        "f3" => Key::F3,
+        "f4" => Key::F4,
+        "f5" => Key::F5,
+        "f6" => Key::F6,
+        "f7" => Key::F7,
+        "f8" => Key::F8,
+        "f9" => Key::F9,
+        "f10" => Key::F10,
+        "f11" => Key::F11,
+        "f12" => Key::F12,
+        _ => return parse_modifier(name).map(char::from),
+    };
+
+    Ok(key.into())
+}
+
+/// Parse a chord such as "Ctrl+Shift+Tab" into the keys to hold, in press order
+///
+/// A literal plus key is written as "+" on its own, or "Ctrl++" in a chord.
+pub fn parse_chord(chord: &str) -> Result<Vec<char>> {
+    let chord = chord.trim();
+    let (modifiers, key) = match chord.strip_suffix("++") {
+        Some(modifiers) => (modifiers, "+"),
+        // Splitting a lone "+" would leave an empty key
+        None if chord == "+" => ("", "+"),
+        None => chord.rsplit_once('+').unwrap_or(("", chord)),
+    };
+
+    let mut keys = modifiers
+        .split('+')
+        .filter(|name| !name.trim().is_empty())
+        .map(|name| parse_modifier(name.trim()).map(char::from))
+        .collect::<Result<Vec<_>>>()?;
+    keys.push(parse_key(key.trim())?);
+
+    Ok(keys)
+}
+
+/// Press and release each chord in turn, holding all keys of a chord together
+pub async fn send_chords(client: &Client, chords: &[Vec<char>]) -> Result<()> {
+    let mut keys = KeyActions::new("keyboard".to_string());
+
+    for chord in chords {
+        for value in chord {
+            keys = keys.then(KeyAction::Down { value: *value });
+        }
+        for value in chord.iter().rev() {
+            keys = keys.then(KeyAction::Up { value: *value });
+        }
+    }
+
+    perform(client, keys).await
+}
+
+/// Move the pointer over the center of an element
+pub async fn hover(client: &Client, elem: &Element) -> Result<()> {
+    let mouse = MouseActions::new("mouse".to_string()).then(move_to(elem));
+
+    perform(client, mouse).await
+}
+
+/// Click an element `count` times with the given button while holding `modifiers`
+pub async fn click(
+    client: &Client,
+    elem: &Element,
+    button: u64,
+    count: usize,
+    modifiers: &[Key],
+) -> Result<()> {
+    let mut keys = KeyActions::new("keyboard".to_string());
+    let mut mouse = MouseActions::new("mouse".to_string());
+
+    // Press modifiers first, one tick each
+    for modifier in modifiers {
+        keys = keys.then(KeyAction::Down {
+            value: (*modifier).into(),
+        });
+        mouse = mouse.pause(Duration::ZERO);
+    }
+
+    keys = keys.pause(Duration::ZERO);
+    mouse = mouse.then(move_to(elem));
+
+    for _ in 0..count {
+        keys = keys.pause(Duration::ZERO).pause(Duration::ZERO);
+        mouse = mouse
+            .then(PointerAction::Down { button })
+            .then(PointerAction::Up { button });
+    }
+
+    // Release modifiers in reverse order
+    for modifier in modifiers.iter().rev() {
+        keys = keys.then(KeyAction::Up {
+            value: (*modifier).into(),
+        });
+        mouse = mouse.pause(Duration::ZERO);
+    }
+
+    perform(client, Actions::from(keys).and(mouse)).await
+}
+
+/// Press the left button on `source`, move to `target` and release it there
+pub async fn drag_to(client: &Client, source: &Element, target: &Element) -> Result<()> {
+    let mouse = MouseActions::new("mouse".to_string())
+        .then(move_to(source))
+        .then(PointerAction::Down {
+            button: MOUSE_BUTTON_LEFT,
+        })
+        .pause(Duration::from_millis(150))
+        .then(PointerAction::MoveToElement {
+            element: target.clone(),
+            duration: Some(Duration::from_millis(300)),
+            x: 0,
+            y: 0,
+        })
+        .then(PointerAction::Up {
+            button: MOUSE_BUTTON_LEFT,
+        });
+
+    perform(client, mouse).await
+}
+
+fn move_to(elem: &Element) -> PointerAction {
+    PointerAction::MoveToElement {
+        element: elem.clone(),
+        duration: None,
+        x: 0,
+        y: 0,
+    }
+}
+
+/// Perform the action sequences, then release any held inputs
+async fn perform(client: &Client, actions: impl Into<Actions>) -> Result<()> {
+    client
+        .perform_actions(actions)
+        .await
+        .context("Failed to perform input actions")?;
+
+    client
+        .release_actions()
+        .await
+        .context("Failed to release input actions")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_chord_plus_alone() {
+        assert_eq!(parse_chord("+").unwrap(), vec!['+']);
+    }
+
+    #[test]
+    fn parse_chord_plus_with_modifier() {
+        assert_eq!(
+            parse_chord("Ctrl++").unwrap(),
+            vec![char::from(Key::Control), '+']
+        );
+    }
+
+    #[test]
+    fn parse_chord_modifiers_in_order() {
+        assert_eq!(
+            parse_chord("Ctrl+Shift+Tab").unwrap(),
+            vec![
+                char::from(Key::Control),
+                char::from(Key::Shift),
+                char::from(Key::Tab)
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_chord_named_key() {
+        assert_eq!(
+            parse_chord("Escape").unwrap(),
+            vec![char::from(Key::Escape)]
+        );
+    }
+}
@@ -1,14 +1,154 @@
 use anyhow::{Context, Result};
+use fantoccini::actions::{MOUSE_BUTTON_LEFT, MOUSE_BUTTON_RIGHT};
+use fantoccini::elements::Element;
+use fantoccini::error::{CmdError, ErrorStatus};
+use fantoccini::key::Key;
+use fantoccini::wd::TimeoutConfiguration;
 use fantoccini::{Client, Locator};
-use log::{error, info};
+use log::{error, info, warn};
 use rand::Rng;
+use regex::Regex;
+use serde_json::Value;
+use std::fmt;
+use std::fs;
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, Instant};
 
-use crate::models::Instruction;
+use crate::artifacts;
+use crate::assertions::AssertionFailure;
+use crate::context::{ElementScope, RunContext};
+use crate::control_flow;
+use crate::cookie_manager;
+use crate::dialog_manager;
+use crate::download_manager;
+use crate::extraction::{self, Source};
+use crate::input_actions;
+use crate::models::{ClickMode, Instruction, ScriptArg, StorageOp, TableFormat, WaitCondition};
+use crate::output_sink::Record;
+use crate::storage_manager::{self, StorageArea};
 use crate::timing_utils::random_wait_time;
+use crate::window_manager;
+
+/// Key under which WebDriver serializes element references
+const ELEMENT_KEY: &str = "element-6066-11e4-a52e-4f735466cecf";
+
+pub async fn handle_instruction(
+    client: &mut Client,
+    instruction: &Instruction,
+    ctx: &mut RunContext,
+) -> Result<()> {
+    let rendered = ctx.render_instruction(instruction)?;
+    let instruction = &rendered;
+
+    // Blocks run each nested instruction through this function, so skip the per-step handling
+    // apart from entering their frames, which their steps then run in
+    if instruction.is_block() {
+        let frames = instruction.frame_path().unwrap_or_default();
+        let (entered, mut result) = enter_frames(client, frames).await;
+        if result.is_ok() {
+            result = Box::pin(control_flow::run_block(client, instruction, ctx)).await;
+        }
+        return leave_frames(client, entered, result).await;
+    }
+
+    // Deal with a dialog left open by the previous instruction before this one starts
+    if !matches!(instruction, Instruction::HandleDialog { .. }) {
+        dialog_manager::handle_open_dialog(client, ctx.unexpected_dialog).await?;
+    }
+
+    // Remember the open windows so that new ones can be detected afterwards
+    if !matches!(instruction, Instruction::WaitForNewWindow { .. }) {
+        if let Ok(windows) = client.windows().await {
+            ctx.window_handles = windows;
+        }
+    }
+
+    let frames = instruction.frame_path().unwrap_or_default();
+    let (entered, mut result) = enter_frames(client, frames).await;
+
+    if result.is_ok() {
+        result = run_instruction(client, instruction, ctx, instruction.shadow_hosts()).await;
+
+        // A dialog opened part-way through: apply the domain policy so later instructions can
+        // run, but don't retry, since the instruction may already have had side effects
+        if let Err(e) = result {
+            result = if dialog_manager::is_blocked_by_dialog(&e) {
+                match dialog_manager::apply_policy(client, ctx.unexpected_dialog).await {
+                    Ok(()) => Err(e.context("A dialog opened while the instruction was running")),
+                    Err(policy_error) => Err(policy_error),
+                }
+            } else {
+                Err(e)
+            };
+        }
+    }
+
+    let result = leave_frames(client, entered, result).await;
+
+    let clicked = matches!(
+        instruction,
+        Instruction::Click { .. } | Instruction::RandomClick { .. }
+    );
+    if result.is_ok() && clicked {
+        if ctx.follow_new_windows {
+            window_manager::follow_new_window(client, &ctx.window_handles).await?;
+        } else if client
+            .windows()
+            .await
+            .is_ok_and(|windows| windows.len() > ctx.window_handles.len())
+        {
+            warn!("Click opened a new window, but the automator stays on the current one; use wait_for_new_window or follow_new_windows to switch");
+        }
+    }
+
+    result
+}
+
+/// Enter a frame path relative to the current frame, returning how many frames were entered
+pub(crate) async fn enter_frames(client: &Client, frames: &[String]) -> (usize, Result<()>) {
+    for (entered, frame) in frames.iter().enumerate() {
+        if let Err(e) = enter_frame(client, frame, None, Scope::default(), None).await {
+            return (entered, Err(e));
+        }
+    }
+    (frames.len(), Ok(()))
+}
+
+/// Return to the frame `entered` levels up, without masking an error of the instruction itself
+pub(crate) async fn leave_frames<T>(
+    client: &Client,
+    entered: usize,
+    result: Result<T>,
+) -> Result<T> {
+    for _ in 0..entered {
+        let left = client
+            .enter_parent_frame()
+            .await
+            .context("Failed to leave frame");
+        if let Err(e) = left {
+            if result.is_ok() {
+                return Err(e);
+            }
+            warn!("{:#}", e);
+            break;
+        }
+    }
+    result
+}
+
+async fn run_instruction(
+    client: &mut Client,
+    instruction: &Instruction,
+    ctx: &mut RunContext,
+    shadow: Option<&[String]>,
+) -> Result<()> {
+    // Cloned so that instructions can update the context while holding the scope
+    let scope_path = ctx.scope.clone();
+    let scope = Scope {
+        path: &scope_path,
+        shadow,
+    };
 
-pub async fn handle_instruction(client: &mut Client, instruction: &Instruction) -> Result<()> {
     match instruction {
         Instruction::Navigate { url, critical } => {
             info!("Navigating to URL: {}", url);
@@ -22,6 +162,64 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
             nav_result.context("Navigation failed")?;
         }
 
+        Instruction::Back {
+            wait_for_load,
+            timeout,
+        } => {
+            info!("Going back");
+            client.back().await.context("Failed to go back")?;
+            if wait_for_load.unwrap_or(false) {
+                wait_for_page_load(client, *timeout).await?;
+            }
+        }
+
+        Instruction::Forward {
+            wait_for_load,
+            timeout,
+        } => {
+            info!("Going forward");
+            client.forward().await.context("Failed to go forward")?;
+            if wait_for_load.unwrap_or(false) {
+                wait_for_page_load(client, *timeout).await?;
+            }
+        }
+
+        Instruction::Refresh {
+            wait_for_load,
+            timeout,
+        } => {
+            info!("Refreshing page");
+            client.refresh().await.context("Failed to refresh page")?;
+            if wait_for_load.unwrap_or(false) {
+                wait_for_page_load(client, *timeout).await?;
+            }
+        }
+
+        Instruction::SetWindow {
+            width,
+            height,
+            x,
+            y,
+            viewport,
+            maximize,
+        } => {
+            if maximize.unwrap_or(false) {
+                info!("Maximizing window");
+                client
+                    .maximize_window()
+                    .await
+                    .context("Failed to maximize window")?;
+            } else {
+                window_manager::set_rect(
+                    client,
+                    (*x, *y),
+                    (*width, *height),
+                    viewport.unwrap_or(false),
+                )
+                .await?;
+            }
+        }
+
         Instruction::Click {
             selector,
             by,
@@ -29,45 +227,31 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
             delay,
             delay_stdev,
             ignore_errors,
+            mode,
+            scroll_into_view,
+            ..
         } => {
             info!("Clicking on element: {}", selector);
 
-            // Determine locator based on 'by' parameter
-            let locator = match by.as_deref() {
-                Some("id") => Locator::Id(selector),
-                Some("xpath") => Locator::XPath(selector),
-                _ => Locator::Css(selector),
-            };
-
-            // Set default timeout or use provided value
-            let wait_timeout = timeout.unwrap_or(10);
-
-            // Use a custom timeout implementation
-            let elem =
-                tokio::time::timeout(Duration::from_secs(wait_timeout), client.find(locator)).await;
-
-            // Check if the timeout occurred
-            if ignore_errors.unwrap_or(false) && elem.is_err() {
-                info!("Click ignored due to error: {}", elem.err().unwrap());
-                return Ok(());
-            }
-
-            let elem = elem.context("Timeout waiting for element")?;
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await;
 
+            // Check if the element could not be found in time
             if ignore_errors.unwrap_or(false) && elem.is_err() {
                 info!("Click ignored due to error: {}", elem.err().unwrap());
                 return Ok(());
             }
 
             // Unwrap element
-            let elem = elem.context("Failed to find element")?;
+            let elem = elem?;
 
-            // Click the element by invoking the click method within JS runtime
-            client
-                .execute("arguments[0].click()", vec![serde_json::to_value(elem)?])
-                .await
-                .context("Failed to click element")?;
-            // elem.click().await.context("Failed to click element")?;
+            click_element(
+                client,
+                &elem,
+                selector,
+                mode.unwrap_or_default(),
+                scroll_into_view.unwrap_or(false),
+            )
+            .await?;
 
             if let Some(delay) = delay {
                 let wait_time = if let Some(delay_stdev) = delay_stdev {
@@ -81,6 +265,437 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
             }
         }
 
+        Instruction::Type {
+            selector,
+            by,
+            timeout,
+            text,
+            clear,
+            key_delay,
+            key_delay_stdev,
+            press_enter,
+            ignore_errors,
+            ..
+        } => {
+            info!("Typing into element: {}", selector);
+
+            let elem = match find_element(client, selector, by.as_deref(), scope, *timeout).await {
+                Ok(elem) => elem,
+                Err(e) if ignore_errors.unwrap_or(false) => {
+                    info!("Typing ignored due to error: {}", e);
+                    return Ok(());
+                }
+                Err(e) => return Err(e),
+            };
+
+            if clear.unwrap_or(false) {
+                elem.clear().await.context("Failed to clear element")?;
+            }
+
+            if let Some(key_delay) = key_delay {
+                // Type character by character with a randomized pause between keys
+                for c in text.chars() {
+                    elem.send_keys(&c.to_string())
+                        .await
+                        .context("Failed to type into element")?;
+
+                    let wait_time = match key_delay_stdev {
+                        Some(std_dev) => random_wait_time(*key_delay, *std_dev)?,
+                        None => *key_delay,
+                    };
+                    sleep(Duration::from_secs_f64(wait_time)).await;
+                }
+            } else {
+                elem.send_keys(text)
+                    .await
+                    .context("Failed to type into element")?;
+            }
+
+            if press_enter.unwrap_or(false) {
+                elem.send_keys(&Key::Enter)
+                    .await
+                    .context("Failed to press Enter")?;
+            }
+        }
+
+        Instruction::Select {
+            selector,
+            by,
+            timeout,
+            text,
+            value,
+            index,
+            ..
+        } => {
+            info!("Selecting option in element: {}", selector);
+
+            let given = [text.is_some(), value.is_some(), index.is_some()];
+            if given.iter().filter(|&&set| set).count() > 1 {
+                return Err(anyhow::anyhow!(
+                    "Select takes only one of 'text', 'value' and 'index': {}",
+                    selector
+                ));
+            }
+
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+
+            if let Some(text) = text {
+                elem.select_by_label(text)
+                    .await
+                    .with_context(|| format!("Failed to select option with text: {}", text))?;
+            } else if let Some(value) = value {
+                elem.select_by_value(value)
+                    .await
+                    .with_context(|| format!("Failed to select option with value: {}", value))?;
+            } else if let Some(index) = index {
+                elem.select_by_index(*index)
+                    .await
+                    .with_context(|| format!("Failed to select option at index: {}", index))?;
+            } else {
+                return Err(anyhow::anyhow!(
+                    "Select requires one of 'text', 'value' or 'index': {}",
+                    selector
+                ));
+            }
+        }
+
+        Instruction::SetChecked {
+            selector,
+            by,
+            timeout,
+            checked,
+            ..
+        } => {
+            let checked = checked.unwrap_or(true);
+            info!("Setting checked state of {} to {}", selector, checked);
+
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+
+            let is_checked = elem
+                .is_selected()
+                .await
+                .context("Failed to read checked state")?;
+
+            if is_checked == checked {
+                info!("Element already in desired state: {}", selector);
+            } else {
+                let input_type = elem.attr("type").await?.unwrap_or_default();
+                if input_type.eq_ignore_ascii_case("radio") && !checked {
+                    return Err(anyhow::anyhow!(
+                        "Cannot uncheck radio button by clicking it: {}",
+                        selector
+                    ));
+                }
+
+                client
+                    .execute("arguments[0].click()", vec![serde_json::to_value(&elem)?])
+                    .await
+                    .context("Failed to click element")?;
+            }
+        }
+
+        Instruction::Hover {
+            selector,
+            by,
+            timeout,
+            ..
+        } => {
+            info!("Hovering over element: {}", selector);
+
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            input_actions::hover(client, &elem).await?;
+        }
+
+        Instruction::DoubleClick {
+            selector,
+            by,
+            timeout,
+            modifiers,
+            ..
+        } => {
+            info!("Double-clicking on element: {}", selector);
+
+            let modifiers = input_actions::parse_modifiers(modifiers.as_deref())?;
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            input_actions::click(client, &elem, MOUSE_BUTTON_LEFT, 2, &modifiers).await?;
+        }
+
+        Instruction::ContextClick {
+            selector,
+            by,
+            timeout,
+            modifiers,
+            ..
+        } => {
+            info!("Right-clicking on element: {}", selector);
+
+            let modifiers = input_actions::parse_modifiers(modifiers.as_deref())?;
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            input_actions::click(client, &elem, MOUSE_BUTTON_RIGHT, 1, &modifiers).await?;
+        }
+
+        Instruction::ModifierClick {
+            selector,
+            by,
+            timeout,
+            modifiers,
+            ..
+        } => {
+            info!("Clicking on element {} with {:?}", selector, modifiers);
+
+            let modifiers = input_actions::parse_modifiers(Some(modifiers))?;
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            input_actions::click(client, &elem, MOUSE_BUTTON_LEFT, 1, &modifiers).await?;
+        }
+
+        Instruction::DragTo {
+            selector,
+            by,
+            timeout,
+            target,
+            target_by,
+            ..
+        } => {
+            info!("Dragging element {} to {}", selector, target);
+
+            let source = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            let target =
+                find_element(client, target, target_by.as_deref(), scope, *timeout).await?;
+            input_actions::drag_to(client, &source, &target).await?;
+        }
+
+        Instruction::Keys {
+            keys,
+            selector,
+            by,
+            timeout,
+            ..
+        } => {
+            info!("Pressing keys: {:?}", keys);
+
+            // Parse every chord up front so a typo doesn't leave keys half-sent
+            let chords = keys
+                .iter()
+                .map(|chord| input_actions::parse_chord(chord))
+                .collect::<Result<Vec<_>>>()?;
+
+            if let Some(selector) = selector {
+                let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+                client
+                    .execute("arguments[0].focus()", vec![serde_json::to_value(&elem)?])
+                    .await
+                    .context("Failed to focus element")?;
+            }
+
+            input_actions::send_chords(client, &chords).await?;
+        }
+
+        Instruction::SwitchToFrame {
+            selector,
+            by,
+            timeout,
+            index,
+        } => {
+            if let Some(selector) = selector {
+                info!("Switching to frame: {}", selector);
+                enter_frame(client, selector, by.as_deref(), scope, *timeout).await?;
+            } else {
+                match index {
+                    Some(index) => info!("Switching to frame #{}", index),
+                    None => info!("Switching to top-level document"),
+                }
+                client
+                    .enter_frame(*index)
+                    .await
+                    .context("Failed to switch frame")?;
+            }
+        }
+
+        Instruction::SwitchToParentFrame => {
+            info!("Switching to parent frame");
+            client
+                .enter_parent_frame()
+                .await
+                .context("Failed to switch to parent frame")?;
+        }
+
+        Instruction::NewTab { url } => {
+            info!("Opening new tab");
+            let tab = client
+                .new_window(true)
+                .await
+                .context("Failed to open new tab")?;
+            client
+                .switch_to_window(tab.handle)
+                .await
+                .context("Failed to switch to new tab")?;
+
+            if let Some(url) = url {
+                info!("Navigating to URL: {}", url);
+                client.goto(url).await.context("Navigation failed")?;
+            }
+        }
+
+        Instruction::SwitchWindow { index, title, url } => {
+            info!("Switching window");
+            let title = title
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("Invalid title pattern")?;
+            let url = url
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("Invalid URL pattern")?;
+
+            window_manager::switch_to_matching(client, *index, title.as_ref(), url.as_ref())
+                .await?;
+        }
+
+        Instruction::CloseWindow => {
+            info!("Closing current window");
+            window_manager::close_current(client).await?;
+        }
+
+        Instruction::WaitForNewWindow { timeout } => {
+            let timeout = Duration::from_secs_f64(timeout.unwrap_or(10.0));
+            info!("Waiting up to {:?} for a new window", timeout);
+            window_manager::wait_for_new_window(client, &ctx.window_handles, timeout).await?;
+        }
+
+        Instruction::HandleDialog {
+            accept,
+            text,
+            store_as,
+            timeout,
+        } => {
+            let timeout = Duration::from_secs_f64(timeout.unwrap_or(5.0));
+            let message = dialog_manager::wait_for_dialog(client, timeout).await?;
+            info!("Handling dialog: {:?}", message);
+
+            if let Some(name) = store_as {
+                ctx.variables.insert(name.clone(), message.into());
+            }
+
+            if let Some(text) = text {
+                client
+                    .send_alert_text(text)
+                    .await
+                    .context("Failed to enter prompt text")?;
+            }
+
+            if accept.unwrap_or(true) {
+                client
+                    .accept_alert()
+                    .await
+                    .context("Failed to accept dialog")?;
+            } else {
+                client
+                    .dismiss_alert()
+                    .await
+                    .context("Failed to dismiss dialog")?;
+            }
+        }
+
+        Instruction::Upload {
+            selector,
+            by,
+            timeout,
+            paths,
+            ..
+        } => {
+            let files = paths
+                .iter()
+                .map(|path| ctx.resolve_existing_path(path))
+                .collect::<Result<Vec<_>>>()?;
+            info!("Uploading {:?} to element: {}", files, selector);
+
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+
+            // File inputs are often hidden behind a styled button; reveal them while sending
+            let hidden = !elem.is_displayed().await.unwrap_or(true);
+            let original_style = if hidden {
+                let style = client
+                    .execute(
+                        "const el = arguments[0];
+                         const style = el.getAttribute('style');
+                         el.style.setProperty('display', 'block', 'important');
+                         el.style.setProperty('visibility', 'visible', 'important');
+                         el.style.setProperty('opacity', '1', 'important');
+                         el.style.setProperty('width', '1px', 'important');
+                         el.style.setProperty('height', '1px', 'important');
+                         return style;",
+                        vec![serde_json::to_value(&elem)?],
+                    )
+                    .await
+                    .context("Failed to reveal file input")?;
+                Some(style)
+            } else {
+                None
+            };
+
+            // Multiple files are sent as one newline-separated list
+            let file_list = files
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join("\n");
+            let sent = elem.send_keys(&file_list).await;
+
+            if let Some(style) = original_style {
+                client
+                    .execute(
+                        "const [el, style] = arguments;
+                         if (style === null) el.removeAttribute('style');
+                         else el.setAttribute('style', style);",
+                        vec![serde_json::to_value(&elem)?, style],
+                    )
+                    .await
+                    .context("Failed to restore file input style")?;
+            }
+
+            sent.context("Failed to send files to input")?;
+        }
+
+        Instruction::WaitForDownload {
+            pattern,
+            min_size,
+            timeout,
+            store_as,
+        } => {
+            let dir = ctx
+                .download_dir
+                .clone()
+                .context("wait_for_download requires a download directory (--download-dir)")?;
+            let pattern = pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("Invalid download pattern")?;
+            let timeout = Duration::from_secs_f64(timeout.unwrap_or(60.0));
+            info!(
+                "Waiting up to {:?} for a download in {}",
+                timeout,
+                dir.display()
+            );
+
+            let path = download_manager::wait_for_download(
+                &dir,
+                pattern.as_ref(),
+                min_size.unwrap_or(0),
+                &ctx.claimed_downloads,
+                timeout,
+            )
+            .await?;
+            ctx.claimed_downloads.insert(path.clone());
+
+            if let Some(name) = store_as {
+                ctx.variables
+                    .insert(name.clone(), path.display().to_string().into());
+            }
+        }
+
         Instruction::Wait { seconds, stdev } => {
             // If no standard deviation is specified, use a fixed wait time
             let wait_time = match stdev {
@@ -88,20 +703,222 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
                 None => *seconds,
             };
 
-            info!("Waiting for {} seconds", wait_time);
+            info!("Waiting for {} seconds", wait_time);
+
+            // Convert to Duration and wait
+            sleep(Duration::from_secs_f64(wait_time)).await;
+        }
+
+        Instruction::WaitFor {
+            condition,
+            selector,
+            by,
+            text,
+            attribute,
+            value,
+            pattern,
+            timeout,
+            interval,
+            ..
+        } => {
+            let condition = ConditionCheck {
+                condition: *condition,
+                selector: selector.as_deref(),
+                by: by.as_deref(),
+                scope,
+                text: text.as_deref(),
+                attribute: attribute.as_deref(),
+                value: value.as_deref(),
+                pattern: pattern
+                    .as_deref()
+                    .map(Regex::new)
+                    .transpose()
+                    .context("Invalid wait_for pattern")?,
+            };
+            condition.validate()?;
+            let timeout = Duration::from_secs_f64(timeout.unwrap_or(10.0));
+            let interval = Duration::from_secs_f64(interval.unwrap_or(0.5));
+            info!("Waiting up to {:?} for {}", timeout, condition);
+
+            // Disable the implicit wait while polling so each check returns immediately
+            let implicit = set_implicit_wait(client, Some(Duration::ZERO)).await?;
+
+            let deadline = Instant::now() + timeout;
+            let satisfied = loop {
+                if condition.check(client).await {
+                    break true;
+                }
+                // Sleep until the next check, making the last one exactly at the deadline
+                let now = Instant::now();
+                if now >= deadline {
+                    break false;
+                }
+                sleep(interval.min(deadline - now)).await;
+            };
+
+            set_implicit_wait(client, implicit).await?;
+
+            if !satisfied {
+                return Err(anyhow::anyhow!(
+                    "Timed out after {:?} waiting for {}",
+                    timeout,
+                    condition
+                ));
+            }
+        }
+
+        Instruction::Scroll {
+            amount,
+            container,
+            by,
+            timeout,
+            ..
+        } => {
+            let scroll_amount = amount.unwrap_or(100);
+
+            if let Some(container) = container {
+                info!("Scrolling container {} by {}", container, scroll_amount);
+                let elem = find_element(client, container, by.as_deref(), scope, *timeout).await?;
+                client
+                    .execute(
+                        "arguments[0].scrollBy(0, arguments[1]);",
+                        vec![serde_json::to_value(&elem)?, scroll_amount.into()],
+                    )
+                    .await
+                    .context("Failed to scroll container")?;
+            } else {
+                let script = format!("window.scrollBy(0, {});", scroll_amount);
+
+                client
+                    .execute(&script, vec![])
+                    .await
+                    .context("Failed to scroll")?;
+            }
+        }
+
+        Instruction::ScrollTo {
+            selector,
+            by,
+            timeout,
+            block,
+            ..
+        } => {
+            info!("Scrolling element into view: {}", selector);
+
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            client
+                .execute(
+                    "arguments[0].scrollIntoView({block: arguments[1], inline: 'nearest'});",
+                    vec![
+                        serde_json::to_value(&elem)?,
+                        serde_json::to_value(block.unwrap_or_default())?,
+                    ],
+                )
+                .await
+                .context("Failed to scroll element into view")?;
+        }
+
+        Instruction::ScrollUntil {
+            selector,
+            by,
+            container,
+            amount,
+            max_iterations,
+            delay,
+            timeout,
+            optional,
+            ..
+        } => {
+            let container = match container {
+                Some(container) => {
+                    Some(find_element(client, container, by.as_deref(), scope, *timeout).await?)
+                }
+                None => None,
+            };
+            let max_iterations = max_iterations.unwrap_or(20);
+            let delay = Duration::from_secs_f64(delay.unwrap_or(1.0));
+            match selector {
+                Some(selector) => info!(
+                    "Scrolling until {} appears, for at most {} scrolls",
+                    selector, max_iterations
+                ),
+                None => info!(
+                    "Scrolling until the content ends, for at most {} scrolls",
+                    max_iterations
+                ),
+            }
+
+            // Scroll the container, or the page without one
+            let scroll = r#"
+                const [container, amount] = arguments;
+                const el = container || document.scrollingElement || document.documentElement;
+                if (amount === null) el.scrollTop = el.scrollHeight;
+                else el.scrollBy(0, amount);
+            "#;
+            let measure = r#"
+                const el = arguments[0] || document.scrollingElement || document.documentElement;
+                return [el.scrollHeight, el.scrollTop + el.clientHeight >= el.scrollHeight - 1];
+            "#;
+            let container = serde_json::to_value(&container)?;
 
-            // Convert to Duration and wait
-            sleep(Duration::from_secs_f64(wait_time)).await;
-        }
+            // Disable the implicit wait so that looking for the element returns immediately
+            let implicit = set_implicit_wait(client, Some(Duration::ZERO)).await?;
+            let result: Result<()> = async {
+                let found = || async {
+                    Ok::<_, anyhow::Error>(match selector {
+                        Some(selector) => !find_elements(client, selector, by.as_deref(), scope)
+                            .await?
+                            .is_empty(),
+                        None => false,
+                    })
+                };
 
-        Instruction::Scroll { amount } => {
-            let scroll_amount = amount.unwrap_or(100);
-            let script = format!("window.scrollBy(0, {});", scroll_amount);
+                let mut scrolls = 0;
+                while scrolls < max_iterations {
+                    if found().await? {
+                        break;
+                    }
 
-            client
-                .execute(&script, vec![])
-                .await
-                .context("Failed to scroll")?;
+                    let before = client.execute(measure, vec![container.clone()]).await?;
+                    client
+                        .execute(scroll, vec![container.clone(), (*amount).into()])
+                        .await
+                        .context("Failed to scroll")?;
+                    scrolls += 1;
+                    sleep(delay).await;
+                    let after = client.execute(measure, vec![container.clone()]).await?;
+
+                    // At the bottom and nothing new was loaded
+                    if after[1].as_bool().unwrap_or(false) && after[0] == before[0] {
+                        info!("Content stopped growing after {} scrolls", scrolls);
+                        break;
+                    }
+                }
+
+                let Some(selector) = selector else {
+                    info!("Stopped scrolling after {} scrolls", scrolls);
+                    return Ok(());
+                };
+                // Check once more, since the last scroll may have loaded the element
+                if found().await? {
+                    info!("Found {} after {} scrolls", selector, scrolls);
+                } else if optional.unwrap_or(false) {
+                    warn!(
+                        "Stopped scrolling after {} scrolls without finding {}",
+                        scrolls, selector
+                    );
+                } else {
+                    return Err(anyhow::anyhow!(
+                        "Stopped scrolling after {} scrolls without finding {}",
+                        scrolls,
+                        selector
+                    ));
+                }
+                Ok(())
+            }
+            .await;
+            set_implicit_wait(client, implicit).await?;
+            result?;
         }
 
         Instruction::RandomClick {
@@ -112,25 +929,21 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
             exhaustive,
             delay,
             delay_stdev,
+            mode,
+            scroll_into_view,
+            ..
         } => {
             info!("Finding random elements to click: {}", selector);
 
             // Set default timeout or use provided value
-            let wait_timeout = timeout.unwrap_or(10);
+            let wait_timeout = Duration::from_secs_f64(timeout.unwrap_or(10.0));
 
             // Loop for exhaustive clicking if enabled
             loop {
-                // Determine locator based on 'by' parameter
-                let locator = match by.as_deref() {
-                    Some("id") => Locator::Id(selector),
-                    Some("xpath") => Locator::XPath(selector),
-                    _ => Locator::Css(selector),
-                };
-
                 // Find all matching elements
                 let elements = tokio::time::timeout(
-                    Duration::from_secs(wait_timeout),
-                    client.find_all(locator),
+                    wait_timeout,
+                    find_elements(client, selector, by.as_deref(), scope),
                 )
                 .await
                 .context("Timeout finding elements");
@@ -139,7 +952,7 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
                     break;
                 }
 
-                let elements = elements?.context("Failed to find elements");
+                let elements = elements?;
 
                 if elements.is_err() {
                     break;
@@ -178,10 +991,14 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
                 let random_index = rand::thread_rng().gen_range(0..filtered_elements.len());
                 let elem = filtered_elements[random_index].clone();
 
-                client
-                    .execute("arguments[0].click()", vec![serde_json::to_value(&elem)?])
-                    .await
-                    .context("Failed to click element")?;
+                click_element(
+                    client,
+                    &elem,
+                    selector,
+                    mode.unwrap_or_default(),
+                    scroll_into_view.unwrap_or(false),
+                )
+                .await?;
 
                 // Add delay if exhaustive is true
                 if exhaustive.unwrap_or(false) {
@@ -203,6 +1020,462 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
                 }
             }
         }
+
+        Instruction::Screenshot {
+            path,
+            selector,
+            by,
+            timeout,
+            full_page,
+            ..
+        } => {
+            let path = ctx.resolve_path(path);
+            info!("Saving screenshot to {}", path.display());
+
+            let png = if let Some(selector) = selector {
+                find_element(client, selector, by.as_deref(), scope, *timeout)
+                    .await?
+                    .screenshot()
+                    .await
+                    .context("Failed to capture element screenshot")?
+            } else if full_page.unwrap_or(false) {
+                artifacts::full_page_screenshot(client).await?
+            } else {
+                client
+                    .screenshot()
+                    .await
+                    .context("Failed to capture screenshot")?
+            };
+
+            artifacts::write_file(&path, png)?;
+        }
+
+        Instruction::ExecuteScript {
+            script,
+            file,
+            args,
+            is_async,
+            store_as,
+        } => {
+            let source = match (script, file) {
+                (Some(_), Some(_)) => {
+                    return Err(anyhow::anyhow!(
+                        "execute_script takes either 'script' or 'file', not both"
+                    ))
+                }
+                (Some(script), None) => script.clone(),
+                (None, Some(file)) => {
+                    let path = ctx.resolve_path(file);
+                    fs::read_to_string(&path).with_context(|| {
+                        format!("Failed to read script file: {}", path.display())
+                    })?
+                }
+                (None, None) => {
+                    return Err(anyhow::anyhow!(
+                        "execute_script requires either 'script' or 'file'"
+                    ))
+                }
+            };
+            info!("Executing custom script");
+
+            let mut script_args = Vec::new();
+            for arg in args.iter().flatten() {
+                let value = match arg {
+                    ScriptArg::Element {
+                        selector,
+                        by,
+                        shadow,
+                    } => serde_json::to_value(
+                        find_element(
+                            client,
+                            selector,
+                            by.as_deref(),
+                            Scope {
+                                path: &scope_path,
+                                shadow: shadow.as_deref(),
+                            },
+                            None,
+                        )
+                        .await?,
+                    )?,
+                    ScriptArg::Variable { var } => ctx
+                        .variables
+                        .get(var)
+                        .cloned()
+                        .with_context(|| format!("Unknown variable: {}", var))?,
+                    ScriptArg::Value(value) => value.clone(),
+                };
+                script_args.push(value);
+            }
+
+            let result = if is_async.unwrap_or(false) {
+                client.execute_async(&source, script_args).await
+            } else {
+                client.execute(&source, script_args).await
+            }
+            .context("Failed to execute script")?;
+
+            if let Some(name) = store_as {
+                info!("Storing script result in variable: {}", name);
+                ctx.variables.insert(name.clone(), result);
+            }
+        }
+
+        Instruction::SetVar { name, value } => {
+            info!("Setting variable {} to {}", name, value);
+            ctx.variables.insert(name.clone(), value.clone());
+        }
+
+        Instruction::SetCookie { cookie } => {
+            info!("Setting cookie: {}", cookie.name);
+            client
+                .add_cookie(cookie_manager::to_webdriver_cookie(cookie.clone()))
+                .await
+                .with_context(|| format!("Failed to set cookie: {}", cookie.name))?;
+        }
+
+        Instruction::DeleteCookie { name } => {
+            info!("Deleting cookie: {}", name);
+            client
+                .delete_cookie(name)
+                .await
+                .with_context(|| format!("Failed to delete cookie: {}", name))?;
+        }
+
+        Instruction::ClearCookies => {
+            info!("Deleting all cookies");
+            client
+                .delete_all_cookies()
+                .await
+                .context("Failed to delete cookies")?;
+        }
+
+        Instruction::SaveCookies { path } => {
+            let path = ctx.resolve_path(path);
+            let count = cookie_manager::save_cookies(client, &path).await?;
+            info!("Saved {} cookies to {}", count, path.display());
+        }
+
+        Instruction::LocalStorage {
+            op,
+            key,
+            value,
+            store_as,
+            path,
+        }
+        | Instruction::SessionStorage {
+            op,
+            key,
+            value,
+            store_as,
+            path,
+        } => {
+            let area = if matches!(instruction, Instruction::LocalStorage { .. }) {
+                StorageArea::Local
+            } else {
+                StorageArea::Session
+            };
+            info!(
+                "Running {} {:?}{}",
+                area.name(),
+                op,
+                key.as_ref()
+                    .map(|k| format!(" on {}", k))
+                    .unwrap_or_default()
+            );
+
+            let result =
+                storage_manager::apply(client, area, *op, key.as_deref(), value.as_ref()).await?;
+
+            if matches!(op, StorageOp::Get | StorageOp::Dump) {
+                if let Some(path) = path {
+                    let path = ctx.resolve_path(path);
+                    artifacts::write_file(&path, serde_json::to_string_pretty(&result)?)?;
+                    info!("Saved {} to {}", area.name(), path.display());
+                }
+                match store_as {
+                    Some(name) => {
+                        ctx.variables.insert(name.clone(), result);
+                    }
+                    None if path.is_none() => info!("{}: {}", area.name(), result),
+                    None => {}
+                }
+            }
+        }
+
+        Instruction::Return { value } => {
+            info!("Returning from instruction file");
+            return Err(control_flow::Return(value.clone()).into());
+        }
+
+        Instruction::AssertText {
+            selector,
+            by,
+            timeout,
+            text,
+            exact,
+            ..
+        } => {
+            info!("Asserting text of element: {}", selector);
+
+            let check = format!("text of {}", selector);
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout)
+                .await
+                .map_err(|_| AssertionFailure::new(&check, format!("{:?}", text), "no element"))?;
+            let actual = elem.text().await.context("Failed to read element text")?;
+
+            let matches = if exact.unwrap_or(false) {
+                actual.trim() == text
+            } else {
+                actual.contains(text.as_str())
+            };
+            if !matches {
+                return Err(AssertionFailure::new(
+                    check,
+                    format!("{:?}", text),
+                    format!("{:?}", actual),
+                )
+                .into());
+            }
+        }
+
+        Instruction::AssertUrl { pattern } => {
+            info!("Asserting URL matches: {}", pattern);
+
+            let regex =
+                Regex::new(pattern).with_context(|| format!("Invalid URL pattern: {}", pattern))?;
+            let actual = client.current_url().await?;
+            if !regex.is_match(actual.as_str()) {
+                return Err(AssertionFailure::new(
+                    "URL",
+                    format!("match for /{}/", pattern),
+                    actual.as_str(),
+                )
+                .into());
+            }
+        }
+
+        Instruction::AssertTitle { title, exact } => {
+            info!("Asserting title: {}", title);
+
+            let actual = client.title().await?;
+            let matches = if exact.unwrap_or(false) {
+                actual.trim() == title
+            } else {
+                actual.contains(title.as_str())
+            };
+            if !matches {
+                return Err(AssertionFailure::new(
+                    "title",
+                    format!("{:?}", title),
+                    format!("{:?}", actual),
+                )
+                .into());
+            }
+        }
+
+        Instruction::AssertElementCount {
+            selector,
+            by,
+            count,
+            min,
+            max,
+            ..
+        } => {
+            info!("Asserting element count of: {}", selector);
+
+            let actual = find_elements(client, selector, by.as_deref(), scope)
+                .await?
+                .len();
+
+            // Without any bounds, assert that at least one element exists
+            let (min, max) = match (count, min, max) {
+                (Some(count), _, _) => (Some(*count), Some(*count)),
+                (None, None, None) => (Some(1), None),
+                (None, min, max) => (*min, *max),
+            };
+            let expected = match (min, max) {
+                (Some(min), Some(max)) if min == max => format!("exactly {}", min),
+                (Some(min), Some(max)) => format!("between {} and {}", min, max),
+                (Some(min), None) => format!("at least {}", min),
+                (None, Some(max)) => format!("at most {}", max),
+                (None, None) => unreachable!(),
+            };
+
+            let in_range =
+                min.is_none_or(|min| actual >= min) && max.is_none_or(|max| actual <= max);
+            if !in_range {
+                return Err(AssertionFailure::new(
+                    format!("count of {}", selector),
+                    expected,
+                    actual.to_string(),
+                )
+                .into());
+            }
+        }
+
+        Instruction::AssertAttribute {
+            selector,
+            by,
+            timeout,
+            attribute,
+            value,
+            ..
+        } => {
+            info!("Asserting attribute {} of element: {}", attribute, selector);
+
+            let check = format!("attribute {} of {}", attribute, selector);
+            let elem = find_element(client, selector, by.as_deref(), scope, *timeout)
+                .await
+                .map_err(|_| AssertionFailure::new(&check, format!("{:?}", value), "no element"))?;
+            let actual = elem
+                .attr(attribute)
+                .await
+                .context("Failed to read element attribute")?;
+
+            if actual.as_deref() != Some(value.as_str()) {
+                return Err(AssertionFailure::new(
+                    check,
+                    format!("{:?}", value),
+                    actual.map_or("no attribute".to_string(), |actual| format!("{:?}", actual)),
+                )
+                .into());
+            }
+        }
+
+        Instruction::Extract {
+            selector,
+            by,
+            timeout,
+            attribute,
+            property,
+            html,
+            pattern,
+            all,
+            optional,
+            store_as,
+            ..
+        } => {
+            let source = Source::new(
+                attribute.as_deref(),
+                property.as_deref(),
+                html.unwrap_or(false),
+            )?;
+            let pattern = pattern
+                .as_deref()
+                .map(Regex::new)
+                .transpose()
+                .context("Invalid extract pattern")?;
+            let all = all.unwrap_or(false);
+            info!("Extracting {} of {} into {}", source, selector, store_as);
+
+            // Wait for the first match, then collect the rest without waiting again
+            let elements =
+                match find_element(client, selector, by.as_deref(), scope, *timeout).await {
+                    Ok(_) if all => find_elements(client, selector, by.as_deref(), scope).await?,
+                    Ok(elem) => vec![elem],
+                    Err(_) if optional.unwrap_or(false) => Vec::new(),
+                    Err(e) => return Err(e),
+                };
+
+            let mut values = Vec::with_capacity(elements.len());
+            for elem in &elements {
+                let value = source.read(elem).await?;
+                let value = match (&pattern, value) {
+                    (Some(pattern), Some(value)) => extraction::capture(pattern, &value),
+                    (_, value) => value,
+                };
+                values.push(value.map_or(Value::Null, Value::String));
+            }
+            let value = if all {
+                Value::Array(values)
+            } else {
+                values.into_iter().next().unwrap_or(Value::Null)
+            };
+
+            info!("{} = {}", store_as, value);
+            if let Some(sink) = ctx.output_sink.as_mut() {
+                sink.write(&Record {
+                    link: ctx.url.clone(),
+                    step: ctx.step,
+                    name: store_as.clone(),
+                    value: value.clone(),
+                })?;
+            }
+            ctx.variables.insert(store_as.clone(), value);
+        }
+
+        Instruction::ExtractTable {
+            selector,
+            by,
+            timeout,
+            row_selector,
+            cell_selector,
+            header_rows,
+            format,
+            path,
+            store_as,
+            ..
+        } => {
+            info!("Extracting table: {}", selector);
+
+            let root = find_element(client, selector, by.as_deref(), scope, *timeout).await?;
+            let table = extraction::read_table(
+                client,
+                &root,
+                row_selector.as_deref(),
+                cell_selector.as_deref(),
+                *header_rows,
+            )
+            .await?;
+
+            let format = format.unwrap_or_default();
+            let name = match path {
+                Some(path) => path.clone(),
+                None => {
+                    // Without a link there is no domain to name the file after
+                    let prefix = match ctx.domain.as_str() {
+                        "" => "table",
+                        domain => domain,
+                    };
+                    format!(
+                        "{}-{}.{}",
+                        prefix,
+                        ctx.render("step{{step}}-{{timestamp}}"),
+                        format.extension()
+                    )
+                }
+            };
+            let path = match &ctx.output_dir {
+                Some(dir) => dir.join(name),
+                None => ctx.resolve_path(&name),
+            };
+            let contents = match format {
+                TableFormat::Csv => table.to_csv(),
+                TableFormat::Json => table.to_json()?,
+            };
+            artifacts::write_file(&path, contents)?;
+            info!(
+                "Saved {} rows of {} columns to {}",
+                table.rows.len(),
+                table.headers.len(),
+                path.display()
+            );
+
+            if let Some(name) = store_as {
+                ctx.variables.insert(name.clone(), table.to_value());
+            }
+        }
+
+        Instruction::If { .. }
+        | Instruction::Repeat { .. }
+        | Instruction::While { .. }
+        | Instruction::ForEach { .. }
+        | Instruction::Retry { .. }
+        | Instruction::Try { .. }
+        | Instruction::Call { .. } => {
+            unreachable!("blocks are run by control_flow::run_block")
+        }
     }
 
     // Small delay between instructions to allow page to process
@@ -210,3 +1483,521 @@ pub async fn handle_instruction(client: &mut Client, instruction: &Instruction)
 
     Ok(())
 }
+
+/// Set the implicit element wait, returning the previous one so it can be restored
+pub(crate) async fn set_implicit_wait(
+    client: &Client,
+    wait: Option<Duration>,
+) -> Result<Option<Duration>> {
+    let previous = client
+        .get_timeouts()
+        .await
+        .context("Failed to read timeouts")?
+        .implicit();
+    client
+        .update_timeouts(TimeoutConfiguration::new(None, None, wait))
+        .await
+        .context("Failed to set implicit wait")?;
+    Ok(previous)
+}
+
+/// Wait until the document has finished loading, giving up after `timeout` seconds (default 10)
+async fn wait_for_page_load(client: &Client, timeout: Option<f64>) -> Result<()> {
+    let timeout = Duration::from_secs_f64(timeout.unwrap_or(10.0));
+    let deadline = Instant::now() + timeout;
+    loop {
+        let state = client
+            .execute("return document.readyState;", vec![])
+            .await
+            .context("Failed to read page load state")?;
+        if state.as_str() == Some("complete") {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "Timed out after {:?} waiting for the page to load",
+                timeout
+            ));
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Build a locator from a selector and its `by` type ("css", "id" or "xpath")
+fn locator<'a>(selector: &'a str, by: Option<&str>) -> Locator<'a> {
+    match by {
+        Some("id") => Locator::Id(selector),
+        Some("xpath") => Locator::XPath(selector),
+        _ => Locator::Css(selector),
+    }
+}
+
+/// Where to look for an instruction's elements
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Scope<'a> {
+    /// Elements selected by enclosing `for_each` blocks, outermost first
+    pub path: &'a [ElementScope],
+    /// Shadow host chain to search within
+    pub shadow: Option<&'a [String]>,
+}
+
+/// Find a single element, giving up after `timeout` seconds (default 10)
+///
+/// Inside a `for_each` block the selector is resolved relative to the current element, and
+/// with a shadow host chain it is resolved inside the innermost shadow root.
+async fn find_element(
+    client: &Client,
+    selector: &str,
+    by: Option<&str>,
+    scope: Scope<'_>,
+    timeout: Option<f64>,
+) -> Result<Element> {
+    let timeout = Duration::from_secs_f64(timeout.unwrap_or(10.0));
+    let root = scope_root(client, scope.path).await?;
+
+    let Some(hosts) = scope.shadow.filter(|hosts| !hosts.is_empty()) else {
+        let found = match &root {
+            Some(root) => tokio::time::timeout(timeout, root.find(locator(selector, by))).await,
+            None => tokio::time::timeout(timeout, client.find(locator(selector, by))).await,
+        };
+        return found
+            .context("Timeout waiting for element")?
+            .with_context(|| format!("Failed to find element: {}", selector));
+    };
+
+    // Shadow roots are searched through JS, which has no implicit wait, so poll
+    let deadline = Instant::now() + timeout;
+    loop {
+        if let Some(elem) = find_in_shadow(client, selector, by, hosts, root.as_ref())
+            .await?
+            .into_iter()
+            .next()
+        {
+            return Ok(elem);
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "Timeout waiting for element {} in shadow root of {}",
+                selector,
+                hosts.join(" > ")
+            ));
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Switch into the iframe element matching a selector
+async fn enter_frame(
+    client: &Client,
+    selector: &str,
+    by: Option<&str>,
+    scope: Scope<'_>,
+    timeout: Option<f64>,
+) -> Result<()> {
+    find_element(client, selector, by, scope, timeout)
+        .await?
+        .enter_frame()
+        .await
+        .with_context(|| format!("Failed to enter frame: {}", selector))
+}
+
+/// Find all elements matching a selector within a scope
+pub(crate) async fn find_elements(
+    client: &Client,
+    selector: &str,
+    by: Option<&str>,
+    scope: Scope<'_>,
+) -> Result<Vec<Element>> {
+    let root = scope_root(client, scope.path).await?;
+    find_all_within(client, selector, by, scope.shadow, root.as_ref()).await
+}
+
+/// Find the current element of the innermost `for_each` block, if any
+///
+/// Elements are found again by index on every call, so navigating away and back inside the
+/// block does not leave stale references behind.
+async fn scope_root(client: &Client, path: &[ElementScope]) -> Result<Option<Element>> {
+    let mut root: Option<Element> = None;
+    for scope in path {
+        let elements = find_all_within(
+            client,
+            &scope.selector,
+            scope.by.as_deref(),
+            scope.shadow.as_deref(),
+            root.as_ref(),
+        )
+        .await?;
+
+        let elem = elements.into_iter().nth(scope.index).with_context(|| {
+            format!(
+                "Element #{} of {} is no longer on the page",
+                scope.index, scope.selector
+            )
+        })?;
+        root = Some(elem);
+    }
+    Ok(root)
+}
+
+/// Find all elements matching a selector below `root` (or the document)
+async fn find_all_within(
+    client: &Client,
+    selector: &str,
+    by: Option<&str>,
+    shadow: Option<&[String]>,
+    root: Option<&Element>,
+) -> Result<Vec<Element>> {
+    if let Some(hosts) = shadow.filter(|hosts| !hosts.is_empty()) {
+        return find_in_shadow(client, selector, by, hosts, root).await;
+    }
+
+    match root {
+        Some(root) => root.find_all(locator(selector, by)).await,
+        None => client.find_all(locator(selector, by)).await,
+    }
+    .with_context(|| format!("Failed to find elements: {}", selector))
+}
+
+/// Resolve a CSS selector inside the shadow root of the last host in `hosts`
+///
+/// The first host is looked up below `root`, or in the whole document without one.
+async fn find_in_shadow(
+    client: &Client,
+    selector: &str,
+    by: Option<&str>,
+    hosts: &[String],
+    root: Option<&Element>,
+) -> Result<Vec<Element>> {
+    if by.is_some_and(|by| by != "css") {
+        return Err(anyhow::anyhow!(
+            "Only css selectors can be used inside shadow roots: {}",
+            selector
+        ));
+    }
+
+    let script = r#"
+        const [hosts, selector, start] = arguments;
+        let root = start || document;
+        for (const host of hosts) {
+            const el = root.querySelector(host);
+            if (!el || !el.shadowRoot) return [];
+            root = el.shadowRoot;
+        }
+        return Array.from(root.querySelectorAll(selector));
+    "#;
+
+    let args = vec![
+        serde_json::to_value(hosts)?,
+        selector.into(),
+        serde_json::to_value(root)?,
+    ];
+    let found = client
+        .execute(script, args)
+        .await
+        .context("Failed to search shadow root")?;
+
+    Ok(found
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|value| value.get(ELEMENT_KEY)?.as_str())
+        .map(|id| Element::from_element_id(client.clone(), id.to_string().into()))
+        .collect())
+}
+
+/// Click an element using the requested strategy
+async fn click_element(
+    client: &Client,
+    elem: &Element,
+    selector: &str,
+    mode: ClickMode,
+    scroll_into_view: bool,
+) -> Result<()> {
+    if scroll_into_view {
+        client
+            .execute(
+                "arguments[0].scrollIntoView({block: 'center', inline: 'center'})",
+                vec![serde_json::to_value(elem)?],
+            )
+            .await
+            .context("Failed to scroll element into view")?;
+    }
+
+    match mode {
+        ClickMode::Js => {
+            // Click the element by invoking the click method within JS runtime
+            client
+                .execute("arguments[0].click()", vec![serde_json::to_value(elem)?])
+                .await
+                .context("Failed to click element")?;
+        }
+        ClickMode::Native => match elem.click().await {
+            Ok(()) => {}
+            Err(CmdError::Standard(e)) if e.error == ErrorStatus::ElementClickIntercepted => {
+                let interceptor = describe_element_at_center(client, elem)
+                    .await
+                    .unwrap_or_else(|| "an unknown element".to_string());
+                return Err(anyhow::anyhow!(
+                    "Click on {} was intercepted by {}: {}",
+                    selector,
+                    interceptor,
+                    e.message
+                ));
+            }
+            Err(e) => return Err(e).context("Failed to click element"),
+        },
+        ClickMode::Actions => {
+            input_actions::click(client, elem, MOUSE_BUTTON_LEFT, 1, &[]).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Describe the topmost element covering the center of `elem`, e.g. `div#cookie-banner.modal`
+async fn describe_element_at_center(client: &Client, elem: &Element) -> Option<String> {
+    let script = r#"
+        const rect = arguments[0].getBoundingClientRect();
+        const el = document.elementFromPoint(rect.left + rect.width / 2, rect.top + rect.height / 2);
+        if (!el) return null;
+        let desc = el.tagName.toLowerCase();
+        if (el.id) desc += '#' + el.id;
+        if (typeof el.className === 'string' && el.className.trim()) {
+            desc += '.' + el.className.trim().split(/\s+/).join('.');
+        }
+        return desc;
+    "#;
+
+    client
+        .execute(script, vec![serde_json::to_value(elem).ok()?])
+        .await
+        .ok()?
+        .as_str()
+        .map(|desc| desc.to_string())
+}
+
+/// A `wait_for` condition together with its parameters
+struct ConditionCheck<'a> {
+    condition: WaitCondition,
+    selector: Option<&'a str>,
+    by: Option<&'a str>,
+    scope: Scope<'a>,
+    text: Option<&'a str>,
+    attribute: Option<&'a str>,
+    value: Option<&'a str>,
+    pattern: Option<Regex>,
+}
+
+impl ConditionCheck<'_> {
+    /// Make sure the condition has the parameters it requires, and none meant for others
+    fn validate(&self) -> Result<()> {
+        let missing = match self.condition {
+            WaitCondition::Url | WaitCondition::Title if self.pattern.is_none() => Some("pattern"),
+            WaitCondition::Url | WaitCondition::Title => None,
+            _ if self.selector.is_none() => Some("selector"),
+            WaitCondition::Text if self.text.is_none() => Some("text"),
+            WaitCondition::Attribute if self.attribute.is_none() => Some("attribute"),
+            WaitCondition::Attribute if self.value.is_none() => Some("value"),
+            _ => None,
+        };
+        if let Some(field) = missing {
+            return Err(anyhow::anyhow!(
+                "wait_for {:?} requires '{}'",
+                self.condition,
+                field
+            ));
+        }
+
+        let takes = |field: &str| match self.condition {
+            WaitCondition::Url | WaitCondition::Title => field == "pattern",
+            WaitCondition::Text => matches!(field, "selector" | "text"),
+            WaitCondition::Attribute => matches!(field, "selector" | "attribute" | "value"),
+            _ => field == "selector",
+        };
+        let given = [
+            ("selector", self.selector.is_some()),
+            ("text", self.text.is_some()),
+            ("attribute", self.attribute.is_some()),
+            ("value", self.value.is_some()),
+            ("pattern", self.pattern.is_some()),
+        ];
+        match given.iter().find(|(field, set)| *set && !takes(field)) {
+            Some((field, _)) => Err(anyhow::anyhow!(
+                "wait_for {:?} does not take '{}'",
+                self.condition,
+                field
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Evaluate the condition once, treating WebDriver errors as "not yet"
+    async fn check(&self, client: &Client) -> bool {
+        self.try_check(client).await.unwrap_or(false)
+    }
+
+    async fn try_check(&self, client: &Client) -> Result<bool> {
+        let pattern_matches = |s: &str| self.pattern.as_ref().is_some_and(|re| re.is_match(s));
+
+        match self.condition {
+            WaitCondition::Url => return Ok(pattern_matches(client.current_url().await?.as_str())),
+            WaitCondition::Title => return Ok(pattern_matches(&client.title().await?)),
+            _ => {}
+        }
+
+        let selector = self.selector.unwrap_or_default();
+        let elements = find_elements(client, selector, self.by, self.scope).await?;
+        let Some(elem) = elements.first() else {
+            return Ok(matches!(
+                self.condition,
+                WaitCondition::Absent | WaitCondition::Hidden
+            ));
+        };
+
+        Ok(match self.condition {
+            WaitCondition::Present => true,
+            WaitCondition::Absent => false,
+            WaitCondition::Visible => elem.is_displayed().await?,
+            WaitCondition::Hidden => !elem.is_displayed().await?,
+            WaitCondition::Enabled => elem.is_enabled().await?,
+            WaitCondition::Clickable => elem.is_displayed().await? && elem.is_enabled().await?,
+            WaitCondition::Text => elem.text().await?.contains(self.text.unwrap_or_default()),
+            WaitCondition::Attribute => {
+                elem.attr(self.attribute.unwrap_or_default()).await? == self.value.map(String::from)
+            }
+            WaitCondition::Url | WaitCondition::Title => unreachable!(),
+        })
+    }
+}
+
+impl fmt::Display for ConditionCheck<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let selector = self.selector.unwrap_or_default();
+        let pattern = self.pattern.as_ref().map_or("", |re| re.as_str());
+        match self.condition {
+            WaitCondition::Present => write!(f, "element {} to be present", selector),
+            WaitCondition::Absent => write!(f, "element {} to be absent", selector),
+            WaitCondition::Visible => write!(f, "element {} to be visible", selector),
+            WaitCondition::Hidden => write!(f, "element {} to be hidden", selector),
+            WaitCondition::Enabled => write!(f, "element {} to be enabled", selector),
+            WaitCondition::Clickable => write!(f, "element {} to be clickable", selector),
+            WaitCondition::Text => write!(
+                f,
+                "element {} to contain text {:?}",
+                selector,
+                self.text.unwrap_or_default()
+            ),
+            WaitCondition::Attribute => write!(
+                f,
+                "attribute {} of {} to equal {:?}",
+                self.attribute.unwrap_or_default(),
+                selector,
+                self.value.unwrap_or_default()
+            ),
+            WaitCondition::Url => write!(f, "URL to match /{}/", pattern),
+            WaitCondition::Title => write!(f, "title to match /{}/", pattern),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(condition: WaitCondition) -> ConditionCheck<'static> {
+        ConditionCheck {
+            condition,
+            selector: None,
+            by: None,
+            scope: Scope::default(),
+            text: None,
+            attribute: None,
+            value: None,
+            pattern: None,
+        }
+    }
+
+    fn error(check: ConditionCheck) -> String {
+        check.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn validate_requires_condition_fields() {
+        assert_eq!(
+            error(check(WaitCondition::Visible)),
+            "wait_for Visible requires 'selector'"
+        );
+        assert_eq!(
+            error(ConditionCheck {
+                selector: Some("#status"),
+                ..check(WaitCondition::Text)
+            }),
+            "wait_for Text requires 'text'"
+        );
+        assert_eq!(
+            error(ConditionCheck {
+                selector: Some("#status"),
+                attribute: Some("aria-busy"),
+                ..check(WaitCondition::Attribute)
+            }),
+            "wait_for Attribute requires 'value'"
+        );
+        assert_eq!(
+            error(check(WaitCondition::Url)),
+            "wait_for Url requires 'pattern'"
+        );
+    }
+
+    #[test]
+    fn validate_rejects_fields_of_other_conditions() {
+        assert_eq!(
+            error(ConditionCheck {
+                selector: Some("#status"),
+                pattern: Some(Regex::new("done").unwrap()),
+                ..check(WaitCondition::Title)
+            }),
+            "wait_for Title does not take 'selector'"
+        );
+        assert_eq!(
+            error(ConditionCheck {
+                selector: Some("#status"),
+                text: Some("Done"),
+                ..check(WaitCondition::Visible)
+            }),
+            "wait_for Visible does not take 'text'"
+        );
+        assert_eq!(
+            error(ConditionCheck {
+                selector: Some("#status"),
+                text: Some("Done"),
+                value: Some("false"),
+                ..check(WaitCondition::Text)
+            }),
+            "wait_for Text does not take 'value'"
+        );
+    }
+
+    #[test]
+    fn validate_accepts_complete_conditions() {
+        assert!(ConditionCheck {
+            selector: Some("#status"),
+            ..check(WaitCondition::Clickable)
+        }
+        .validate()
+        .is_ok());
+        assert!(ConditionCheck {
+            selector: Some("#status"),
+            attribute: Some("aria-busy"),
+            value: Some("false"),
+            ..check(WaitCondition::Attribute)
+        }
+        .validate()
+        .is_ok());
+        assert!(ConditionCheck {
+            pattern: Some(Regex::new("/checkout$").unwrap()),
+            ..check(WaitCondition::Url)
+        }
+        .validate()
+        .is_ok());
+    }
+}
@@ -1,15 +1,33 @@
+pub mod artifacts;
+pub mod assertions;
 pub mod config;
+pub mod context;
+pub mod control_flow;
 pub mod cookie_manager;
+pub mod dialog_manager;
+pub mod download_manager;
+pub mod extraction;
+pub mod input_actions;
 pub mod instruction_handler;
 pub mod link_parser;
 pub mod models;
+pub mod output_sink;
+pub mod storage_manager;
 pub mod timing_utils;
+pub mod window_manager;
 
 use anyhow::Result;
+use assertions::FailedAssertion;
 use config::LoopConfig;
+use context::RunContext;
 use fantoccini::{Client, Locator};
 use log::{info, warn};
+use models::Instruction;
+use output_sink::OutputSink;
 use rand::prelude::SliceRandom;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::PathBuf;
 
 pub struct WebAutomator {
     client: Client,
@@ -18,6 +36,9 @@ pub struct WebAutomator {
     config_path: Option<String>,
     links_file: Option<String>,
     random_order: bool,
+    /// Variables from the config and the command line, which every link starts from
+    base_variables: HashMap<String, Value>,
+    context: RunContext,
 }
 
 impl WebAutomator {
@@ -29,6 +50,11 @@ impl WebAutomator {
         random_order: bool,
     ) -> Self {
         let config = config::ConfigYaml::load_from_file(config_file).unwrap();
+        let base_variables = config.variables.clone();
+        let context = RunContext {
+            variables: base_variables.clone(),
+            ..Default::default()
+        };
         Self {
             client,
             cookie_file,
@@ -36,7 +62,48 @@ impl WebAutomator {
             config_path: Some(config_file.to_string()),
             links_file: links_file.map(|s| s.to_string()),
             random_order,
+            base_variables,
+            context,
+        }
+    }
+
+    /// Set template variables, overriding the config file's defaults
+    pub fn with_variables(mut self, variables: impl IntoIterator<Item = (String, String)>) -> Self {
+        for (name, value) in variables {
+            self.base_variables.insert(name, value.into());
         }
+        self.context.variables = self.base_variables.clone();
+        self
+    }
+
+    /// Directory the browser has been configured to save downloads into
+    pub fn with_download_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.context.download_dir = dir;
+        self
+    }
+
+    /// Save a screenshot and the page source into `dir` whenever an instruction fails
+    pub fn with_failure_artifacts_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.context.failure_artifacts_dir = dir;
+        self
+    }
+
+    /// Write the values read by `extract` instructions to `sink`
+    pub fn with_output_sink(mut self, sink: Option<Box<dyn OutputSink>>) -> Self {
+        self.context.output_sink = sink;
+        self
+    }
+
+    /// Directory `extract_table` writes its files into; relative paths resolve from the
+    /// instruction file otherwise
+    pub fn with_output_dir(mut self, dir: Option<PathBuf>) -> Self {
+        self.context.output_dir = dir;
+        self
+    }
+
+    /// Assertion failures recorded so far
+    pub fn failed_assertions(&self) -> &[FailedAssertion] {
+        &self.context.failed_assertions
     }
 
     pub async fn run_automation(&mut self) -> Result<()> {
@@ -47,10 +114,17 @@ impl WebAutomator {
                 links.shuffle(&mut rand::thread_rng());
             }
             for link in links {
-                let (url, _) = link;
+                let url = link.url;
                 {
                     let domain = config::get_domain_from_url(&url)?;
 
+                    // Start from the base variables, so that columns and values set while
+                    // processing earlier links do not carry over
+                    self.context.variables = self.base_variables.clone();
+                    for (name, value) in link.variables {
+                        self.context.variables.insert(name, value.into());
+                    }
+
                     // Execute instruction for each link
                     self.execute_instruction(&url, &domain).await?;
                 }
@@ -93,6 +167,21 @@ impl WebAutomator {
             cookie_manager::manage_domain_cookies(&mut self.client, url, &cookie_path).await?;
         }
 
+        self.context.url = url.to_string();
+        self.context.domain = domain.to_string();
+        self.context.base_dir = instruction_file
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        let domain_config = self.config.get_domain_config(domain);
+        self.context.follow_new_windows = domain_config
+            .and_then(|config| config.follow_new_windows)
+            .unwrap_or(false);
+        self.context.unexpected_dialog = domain_config
+            .and_then(|config| config.unexpected_dialog)
+            .unwrap_or_default();
+
         // Load loop configuration
         let loop_configs = self
             .config
@@ -102,6 +191,18 @@ impl WebAutomator {
         // Load and parse instructions
         let instructions = config::load_instructions_file(&instruction_file)?;
 
+        if loop_configs
+            .as_ref()
+            .is_some_and(|configs| !configs.is_empty())
+            && contains_loop_block(&instructions)
+        {
+            warn!(
+                "Domain {} mixes loop_config with repeat/while blocks in {}; loop_config indices count top-level instructions only, so consider moving those loops into repeat blocks",
+                domain,
+                instruction_file.display()
+            );
+        }
+
         let mut instruction_index = 0;
         while instruction_index < instructions.len() {
             // Check if current instruction index is the start of a loop
@@ -116,8 +217,9 @@ impl WebAutomator {
                 for _ in 0..loop_config.times {
                     for i in loop_config.from_action_num..=loop_config.to_action_num {
                         if let Some(instruction) = instructions.get(i as usize) {
-                            instruction_handler::handle_instruction(&mut self.client, instruction)
-                                .await?;
+                            if !self.run_step(i as usize, instruction).await? {
+                                return Ok(());
+                            }
                         }
                     }
                 }
@@ -126,7 +228,9 @@ impl WebAutomator {
             } else {
                 // Execute instruction normally
                 if let Some(instruction) = instructions.get(instruction_index) {
-                    instruction_handler::handle_instruction(&mut self.client, instruction).await?;
+                    if !self.run_step(instruction_index, instruction).await? {
+                        return Ok(());
+                    }
                 }
                 instruction_index += 1;
             }
@@ -135,6 +239,29 @@ impl WebAutomator {
         Ok(())
     }
 
+    /// Run a single instruction, recording assertion failures instead of aborting
+    ///
+    /// Returns false when a `return` instruction ends the instruction file early.
+    async fn run_step(&mut self, step: usize, instruction: &Instruction) -> Result<bool> {
+        self.context.step = step;
+        let result = control_flow::run_step(&mut self.client, instruction, &mut self.context).await;
+
+        match result {
+            Ok(()) => Ok(true),
+            Err(e) if e.is::<control_flow::Return>() => {
+                info!("Instruction file returned early at step #{}", step);
+                Ok(false)
+            }
+            Err(e) => {
+                // Assertion failures were already captured where they happened
+                if let Some(dir) = &self.context.failure_artifacts_dir {
+                    artifacts::capture_failure(&self.client, dir, &self.context).await;
+                }
+                Err(e)
+            }
+        }
+    }
+
     async fn check_skip_elements(&mut self, skip_selectors: &[String]) -> bool {
         for selector in skip_selectors {
             match self.client.find(Locator::Css(selector)).await {
@@ -148,3 +275,48 @@ impl WebAutomator {
         false
     }
 }
+
+/// Whether any instruction, at any nesting depth, is a `repeat` or `while` block
+fn contains_loop_block(instructions: &[Instruction]) -> bool {
+    fn check(instruction: &Instruction) -> bool {
+        matches!(
+            instruction,
+            Instruction::Repeat { .. } | Instruction::While { .. }
+        ) || instruction.nested().into_iter().any(check)
+    }
+    instructions.iter().any(check)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(yaml: &str) -> Vec<Instruction> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn contains_loop_block_finds_top_level_loops() {
+        assert!(contains_loop_block(&parse(
+            "- action: repeat\n  times: 2\n  steps:\n    - action: refresh\n"
+        )));
+        assert!(contains_loop_block(&parse(
+            "- action: while\n  condition:\n    exists:\n      selector: '.more'\n  steps:\n    - action: refresh\n"
+        )));
+    }
+
+    #[test]
+    fn contains_loop_block_finds_nested_loops() {
+        assert!(contains_loop_block(&parse(
+            "- action: try\n  steps:\n    - action: refresh\n  catch:\n    - action: if\n      condition:\n        exists:\n          selector: '#retry'\n      then:\n        - action: repeat\n          times: 2\n          steps:\n            - action: refresh\n"
+        )));
+    }
+
+    #[test]
+    fn contains_loop_block_ignores_other_blocks() {
+        assert!(!contains_loop_block(&parse(
+            "- action: refresh\n- action: retry\n  steps:\n    - action: for_each\n      selector: '.item'\n      steps:\n        - action: refresh\n"
+        )));
+        assert!(!contains_loop_block(&[]));
+    }
+}
@@ -4,7 +4,18 @@ use std::fs::File;
 use std::io::{BufRead, BufReader};
 use url::Url;
 
-pub fn parse_links_file(filepath: &str) -> Result<Vec<(String, Option<String>)>> {
+/// A link to process, with its optional comment and variable columns
+#[derive(Debug, Clone)]
+pub struct Link {
+    /// URL to visit
+    pub url: String,
+    /// Text after `#` on the same line
+    pub comment: Option<String>,
+    /// `key=value` columns following the URL
+    pub variables: Vec<(String, String)>,
+}
+
+pub fn parse_links_file(filepath: &str) -> Result<Vec<Link>> {
     let file =
         File::open(filepath).with_context(|| format!("Failed to open links file: {}", filepath))?;
 
@@ -24,7 +35,22 @@ pub fn parse_links_file(filepath: &str) -> Result<Vec<(String, Option<String>)>>
 
         // Split line into potential URL and comment
         let parts: Vec<&str> = line.splitn(2, '#').collect();
-        let url_str = parts[0].trim();
+
+        // The URL may be followed by whitespace-separated key=value columns
+        let mut columns = parts[0].split_whitespace();
+        let url_str = columns.next().unwrap_or_default();
+        let mut variables = Vec::new();
+        for column in columns {
+            match column.split_once('=') {
+                Some((key, value)) if !key.is_empty() => {
+                    variables.push((key.to_string(), value.to_string()))
+                }
+                _ => warn!(
+                    "Ignoring column without key=value in {}: {}",
+                    url_str, column
+                ),
+            }
+        }
 
         // Validate URL
         match Url::parse(url_str) {
@@ -40,7 +66,11 @@ pub fn parse_links_file(filepath: &str) -> Result<Vec<(String, Option<String>)>>
                     url_str,
                     comment.clone().unwrap_or_default()
                 );
-                links.push((url_str.to_string(), comment));
+                links.push(Link {
+                    url: url_str.to_string(),
+                    comment,
+                    variables,
+                });
             }
             Err(_) => {
                 warn!("Invalid URL: {}", url_str);
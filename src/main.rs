@@ -1,9 +1,23 @@
 use anyhow::Result;
 use clap::Parser;
 use fantoccini::ClientBuilder;
-use log::info;
+use log::{error, info};
+use std::path::PathBuf;
 
-use web_automator::WebAutomator;
+use web_automator::output_sink::{self, OutputFormat};
+use web_automator::{download_manager, WebAutomator};
+
+/// Content types Firefox saves straight to the download directory
+const SAVE_TO_DISK_TYPES: [&str; 8] = [
+    "text/csv",
+    "application/csv",
+    "application/pdf",
+    "application/zip",
+    "application/json",
+    "application/octet-stream",
+    "application/vnd.ms-excel",
+    "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+];
 
 /// Web Automator - A flexible web automation tool
 #[derive(Parser, Debug)]
@@ -36,6 +50,51 @@ struct Args {
     /// Enable headless mode
     #[arg(long)]
     headless: bool,
+
+    /// Save downloads under this directory, in a fresh subdirectory per run
+    #[arg(long, value_name = "dir")]
+    download_dir: Option<PathBuf>,
+
+    /// Save a screenshot and the page source here whenever an instruction fails
+    #[arg(long, value_name = "dir")]
+    failure_artifacts: Option<PathBuf>,
+
+    /// Write the values read by extract instructions, and extracted tables, to this directory
+    #[arg(long, value_name = "dir")]
+    output_dir: Option<PathBuf>,
+
+    /// Format of the extracted values file: jsonl, csv or yaml
+    #[arg(long, value_name = "format", default_value = "jsonl")]
+    output_format: OutputFormat,
+
+    /// Initial browser window size
+    #[arg(
+        long,
+        value_name = "WIDTHxHEIGHT",
+        default_value = "1024x3840",
+        value_parser = parse_window_size
+    )]
+    window_size: (u32, u32),
+
+    /// Set a template variable, usable as {{key}} in instructions (repeatable)
+    #[arg(long = "var", value_name = "key=value", value_parser = parse_var)]
+    vars: Vec<(String, String)>,
+}
+
+/// Parse a `WIDTHxHEIGHT` size given to `--window-size`
+fn parse_window_size(s: &str) -> Result<(u32, u32), String> {
+    let parse = |n: &str| n.trim().parse::<u32>().ok();
+    s.split_once(['x', 'X'])
+        .and_then(|(width, height)| Some((parse(width)?, parse(height)?)))
+        .ok_or_else(|| format!("expected WIDTHxHEIGHT, got {:?}", s))
+}
+
+/// Parse a `key=value` pair given to `--var`
+fn parse_var(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("expected key=value, got {:?}", s)),
+    }
 }
 
 #[tokio::main]
@@ -51,6 +110,8 @@ async fn main() -> Result<()> {
     // Set up browser capabilities
     let mut capabilities = serde_json::json!({
         "setWindowRect": true,
+        // Leave unexpected dialogs open so the domain's dialog policy can handle them
+        "unhandledPromptBehavior": "ignore",
         "moz:firefoxOptions": {
             "prefs": {
                 "intl.accept_languages": "en-GB"
@@ -70,6 +131,49 @@ async fn main() -> Result<()> {
     .as_object()
     .unwrap()
     .to_owned();
+
+    // Send downloads to a fresh directory for this run, without prompting
+    let download_dir = match &args.download_dir {
+        Some(base) => {
+            let dir = download_manager::create_run_dir(base)?;
+            info!("Saving downloads to {}", dir.display());
+
+            if let Some(prefs) = capabilities
+                .get_mut("moz:firefoxOptions")
+                .and_then(|options| options.get_mut("prefs"))
+                .and_then(|prefs| prefs.as_object_mut())
+            {
+                prefs.insert("browser.download.folderList".to_string(), 2.into());
+                prefs.insert(
+                    "browser.download.dir".to_string(),
+                    dir.display().to_string().into(),
+                );
+                prefs.insert("browser.download.useDownloadDir".to_string(), true.into());
+                prefs.insert(
+                    "browser.download.always_ask_before_handling_new_types".to_string(),
+                    false.into(),
+                );
+                prefs.insert(
+                    "browser.helperApps.neverAsk.saveToDisk".to_string(),
+                    SAVE_TO_DISK_TYPES.join(",").into(),
+                );
+                prefs.insert("pdfjs.disabled".to_string(), true.into());
+            }
+            capabilities.insert(
+                "goog:chromeOptions".to_string(),
+                serde_json::json!({
+                    "prefs": {
+                        "download.default_directory": dir.display().to_string(),
+                        "download.prompt_for_download": false,
+                        "download.directory_upgrade": true
+                    }
+                }),
+            );
+
+            Some(dir)
+        }
+        None => None,
+    };
     match args.browser.as_str() {
         "firefox" => {
             capabilities.insert(
@@ -91,12 +195,21 @@ async fn main() -> Result<()> {
         }
     }
 
+    let output_sink = match &args.output_dir {
+        Some(dir) => {
+            info!("Writing extracted values to {}", dir.display());
+            Some(output_sink::open(dir, args.output_format)?)
+        }
+        None => None,
+    };
+
     // Create WebDriver client
     let client = ClientBuilder::native()
         .capabilities(capabilities)
         .connect(&args.webdriver)
         .await?;
-    client.set_window_size(1024, 3840).await?;
+    let (width, height) = args.window_size;
+    client.set_window_size(width, height).await?;
 
     // Create WebAutomator instance
     let mut automator = WebAutomator::new(
@@ -105,10 +218,73 @@ async fn main() -> Result<()> {
         &args.config,
         args.links.as_deref(),
         args.random_order,
-    );
+    )
+    .with_variables(args.vars)
+    .with_download_dir(download_dir)
+    .with_failure_artifacts_dir(args.failure_artifacts)
+    .with_output_sink(output_sink)
+    .with_output_dir(args.output_dir);
 
     // Run automation
     automator.run_automation().await?;
 
+    // Exit non-zero when any assertion failed so runs can act as test suites
+    let failed_assertions = automator.failed_assertions();
+    if !failed_assertions.is_empty() {
+        error!("{} assertion(s) failed:", failed_assertions.len());
+        for failed in failed_assertions {
+            error!("  {}", failed);
+        }
+        std::process::exit(1);
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_var_splits_on_first_equals() {
+        assert_eq!(
+            parse_var("query=a=b").unwrap(),
+            ("query".to_string(), "a=b".to_string())
+        );
+        assert_eq!(
+            parse_var("empty=").unwrap(),
+            ("empty".to_string(), String::new())
+        );
+    }
+
+    #[test]
+    fn parse_var_rejects_missing_key_or_equals() {
+        assert!(parse_var("=value").is_err());
+        assert!(parse_var("novalue").is_err());
+    }
+
+    #[test]
+    fn parse_window_size_reads_width_and_height() {
+        assert_eq!(parse_window_size("1280x720"), Ok((1280, 720)));
+        assert_eq!(parse_window_size("375X812"), Ok((375, 812)));
+        assert_eq!(parse_window_size(" 800 x 600 "), Ok((800, 600)));
+    }
+
+    #[test]
+    fn parse_window_size_rejects_malformed_sizes() {
+        for size in [
+            "1280",
+            "1280x",
+            "x720",
+            "1280*720",
+            "-1x720",
+            "1280x720x2",
+            "wide x tall",
+        ] {
+            assert_eq!(
+                parse_window_size(size),
+                Err(format!("expected WIDTHxHEIGHT, got {:?}", size))
+            );
+        }
+    }
+}
@@ -1,7 +1,8 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 /// Represents a browser automation instruction
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "action")]
 pub enum Instruction {
     /// Navigate to a URL
@@ -13,6 +14,50 @@ pub enum Instruction {
         critical: Option<bool>,
     },
 
+    /// Go back one page in the browser history
+    #[serde(rename = "back")]
+    Back {
+        /// Optional: wait until the page has finished loading
+        wait_for_load: Option<bool>,
+        /// Timeout in seconds for the page load (default: 10)
+        timeout: Option<f64>,
+    },
+
+    /// Go forward one page in the browser history
+    #[serde(rename = "forward")]
+    Forward {
+        /// Optional: wait until the page has finished loading
+        wait_for_load: Option<bool>,
+        /// Timeout in seconds for the page load (default: 10)
+        timeout: Option<f64>,
+    },
+
+    /// Reload the current page
+    #[serde(rename = "refresh")]
+    Refresh {
+        /// Optional: wait until the page has finished loading
+        wait_for_load: Option<bool>,
+        /// Timeout in seconds for the page load (default: 10)
+        timeout: Option<f64>,
+    },
+
+    /// Resize, move or maximize the browser window
+    #[serde(rename = "set_window")]
+    SetWindow {
+        /// Optional width in pixels
+        width: Option<u32>,
+        /// Optional height in pixels
+        height: Option<u32>,
+        /// Optional horizontal position of the window on the screen
+        x: Option<u32>,
+        /// Optional vertical position of the window on the screen
+        y: Option<u32>,
+        /// Optional: treat width and height as the viewport size instead of the window size
+        viewport: Option<bool>,
+        /// Optional: maximize the window, ignoring the other fields
+        maximize: Option<bool>,
+    },
+
     /// Click an element
     #[serde(rename = "click")]
     Click {
@@ -20,14 +65,278 @@ pub enum Instruction {
         selector: String,
         /// Selector type: "css", "id", or "xpath"
         by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
         /// Click timeout in seconds
-        timeout: Option<u64>,
+        timeout: Option<f64>,
         /// Optional delay between clicks in seconds
         delay: Option<f64>,
         /// Optional standard deviation for delay
         delay_stdev: Option<f64>,
         /// Optional: ignore if element is not found
         ignore_errors: Option<bool>,
+        /// Optional click strategy: "js", "native" or "actions" (default: "js")
+        mode: Option<ClickMode>,
+        /// Optional: scroll the element into view before clicking
+        scroll_into_view: Option<bool>,
+    },
+
+    /// Type text into an input element
+    #[serde(rename = "type")]
+    Type {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Text to type
+        text: String,
+        /// Optional: clear the field before typing
+        clear: Option<bool>,
+        /// Optional delay between keystrokes in seconds
+        key_delay: Option<f64>,
+        /// Optional standard deviation for keystroke delay
+        key_delay_stdev: Option<f64>,
+        /// Optional: press Enter after typing
+        press_enter: Option<bool>,
+        /// Optional: ignore if element is not found
+        ignore_errors: Option<bool>,
+    },
+
+    /// Select an option of a `<select>` element by exactly one of `text`, `value` and `index`
+    #[serde(rename = "select")]
+    Select {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Select the option with this visible text
+        text: Option<String>,
+        /// Select the option with this value
+        value: Option<String>,
+        /// Select the option at this index
+        index: Option<usize>,
+    },
+
+    /// Drive a checkbox or radio button to the desired state
+    #[serde(rename = "set_checked")]
+    SetChecked {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Desired state, defaults to true
+        checked: Option<bool>,
+    },
+
+    /// Move the pointer over an element
+    #[serde(rename = "hover")]
+    Hover {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+    },
+
+    /// Double-click an element
+    #[serde(rename = "double_click")]
+    DoubleClick {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional modifier keys to hold: "ctrl", "shift", "alt" or "meta"
+        modifiers: Option<Vec<String>>,
+    },
+
+    /// Right-click an element to open its context menu
+    #[serde(rename = "context_click")]
+    ContextClick {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional modifier keys to hold: "ctrl", "shift", "alt" or "meta"
+        modifiers: Option<Vec<String>>,
+    },
+
+    /// Click an element while holding modifier keys
+    #[serde(rename = "modifier_click")]
+    ModifierClick {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Modifier keys to hold: "ctrl", "shift", "alt" or "meta"
+        modifiers: Vec<String>,
+    },
+
+    /// Drag an element and drop it onto another element
+    #[serde(rename = "drag_to")]
+    DragTo {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Drop target selector
+        target: String,
+        /// Drop target selector type: "css", "id", or "xpath"
+        target_by: Option<String>,
+    },
+
+    /// Press special keys and key chords, e.g. "Escape" or "Ctrl+Enter"
+    #[serde(rename = "keys")]
+    Keys {
+        /// Keys or chords to press in order
+        keys: Vec<String>,
+        /// Optional element selector to focus first, otherwise the focused element receives the keys
+        selector: Option<String>,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+    },
+
+    /// Switch into an iframe, or back to the top-level document
+    #[serde(rename = "switch_to_frame")]
+    SwitchToFrame {
+        /// Optional iframe element selector
+        selector: Option<String>,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional frame index, used when no selector is given
+        index: Option<u16>,
+    },
+
+    /// Switch to the parent of the current frame
+    #[serde(rename = "switch_to_parent_frame")]
+    SwitchToParentFrame,
+
+    /// Open a new tab and switch to it
+    #[serde(rename = "new_tab")]
+    NewTab {
+        /// Optional URL to open in the new tab
+        url: Option<String>,
+    },
+
+    /// Switch to another window or tab
+    #[serde(rename = "switch_window")]
+    SwitchWindow {
+        /// Optional window index, in the order the browser reports them
+        index: Option<usize>,
+        /// Optional regular expression the window title must match
+        title: Option<String>,
+        /// Optional regular expression the window URL must match
+        url: Option<String>,
+    },
+
+    /// Close the current window and switch to the first remaining one
+    #[serde(rename = "close_window")]
+    CloseWindow,
+
+    /// Wait for a window opened by the previous instruction and switch to it
+    #[serde(rename = "wait_for_new_window")]
+    WaitForNewWindow {
+        /// Timeout in seconds
+        timeout: Option<f64>,
+    },
+
+    /// Accept or dismiss a JavaScript alert, confirm or prompt dialog
+    #[serde(rename = "handle_dialog")]
+    HandleDialog {
+        /// Optional: accept the dialog, or dismiss it when false (default: true)
+        accept: Option<bool>,
+        /// Optional text to enter into a prompt dialog
+        text: Option<String>,
+        /// Optional variable name to store the dialog message under
+        store_as: Option<String>,
+        /// Time to wait for the dialog to appear, in seconds
+        timeout: Option<f64>,
+    },
+
+    /// Attach local files to an `<input type=file>` element
+    #[serde(rename = "upload")]
+    Upload {
+        /// File input selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Files to attach, relative to the instruction file
+        paths: Vec<String>,
+    },
+
+    /// Wait until a download finishes in the run's download directory
+    #[serde(rename = "wait_for_download")]
+    WaitForDownload {
+        /// Optional regular expression the file name must match
+        pattern: Option<String>,
+        /// Optional minimum file size in bytes
+        min_size: Option<u64>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional variable name to store the downloaded file path under
+        store_as: Option<String>,
     },
 
     /// Wait for a specified duration, optionally with randomized variance
@@ -41,11 +350,90 @@ pub enum Instruction {
         stdev: Option<f64>,
     },
 
-    /// Scroll the page
+    /// Poll until a condition holds
+    #[serde(rename = "wait_for")]
+    WaitFor {
+        /// Condition to wait for
+        condition: WaitCondition,
+        /// Element selector, required by element conditions
+        selector: Option<String>,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Text the element must contain, for the "text" condition
+        text: Option<String>,
+        /// Attribute name, for the "attribute" condition
+        attribute: Option<String>,
+        /// Expected attribute value, for the "attribute" condition
+        value: Option<String>,
+        /// Regular expression, for the "url" and "title" conditions
+        pattern: Option<String>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Polling interval in seconds
+        interval: Option<f64>,
+    },
+
+    /// Scroll the page, or a scrollable container element
     #[serde(rename = "scroll")]
     Scroll {
         /// Scroll amount in pixels
         amount: Option<i64>,
+        /// Optional selector of a scrollable container to scroll instead of the page
+        container: Option<String>,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+    },
+
+    /// Scroll an element into view
+    #[serde(rename = "scroll_to")]
+    ScrollTo {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional vertical alignment: "start", "center", "end" or "nearest" (default: "center")
+        block: Option<ScrollBlock>,
+    },
+
+    /// Keep scrolling to load more content until an element appears or the content ends
+    #[serde(rename = "scroll_until")]
+    ScrollUntil {
+        /// Optional selector of the element to scroll until it appears
+        selector: Option<String>,
+        /// Selector type for both selectors: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Optional selector of a scrollable container to scroll instead of the page
+        container: Option<String>,
+        /// Optional scroll amount in pixels per iteration (default: to the bottom)
+        amount: Option<i64>,
+        /// Optional upper bound on the number of scrolls (default: 20)
+        max_iterations: Option<u32>,
+        /// Seconds to wait for content to load after each scroll (default: 1)
+        delay: Option<f64>,
+        /// Timeout in seconds for finding the container
+        timeout: Option<f64>,
+        /// Optional: only warn instead of failing when `selector` never appears
+        optional: Option<bool>,
     },
 
     /// Click a random element matching a selector
@@ -55,34 +443,693 @@ pub enum Instruction {
         selector: String,
         /// Selector type: "css", "id", or "xpath"
         by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
         /// Optional list of text substrings to exclude
         exclude_text: Option<Vec<String>>,
         /// Optional timeout in seconds
-        timeout: Option<u64>,
+        timeout: Option<f64>,
         /// Optional exhaustive mode to click all elements
         exhaustive: Option<bool>,
         /// Optional delay between clicks in seconds
         delay: Option<f64>,
         /// Optional standard deviation for delay
         delay_stdev: Option<f64>,
+        /// Optional click strategy: "js", "native" or "actions" (default: "js")
+        mode: Option<ClickMode>,
+        /// Optional: scroll the element into view before clicking
+        scroll_into_view: Option<bool>,
+    },
+
+    /// Save a screenshot of the page or of a single element
+    #[serde(rename = "screenshot")]
+    Screenshot {
+        /// Output file path relative to the instruction file; may use {{domain}}, {{url}},
+        /// {{step}} and {{timestamp}}
+        path: String,
+        /// Optional element selector to capture only that element
+        selector: Option<String>,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional: capture the full scrollable page instead of the viewport
+        full_page: Option<bool>,
+    },
+
+    /// Run custom JavaScript, optionally storing its result in a variable
+    #[serde(rename = "execute_script")]
+    ExecuteScript {
+        /// Inline script source; takes either this or `file`
+        script: Option<String>,
+        /// Path to a script file, relative to the instruction file
+        file: Option<String>,
+        /// Optional arguments, available to the script as `arguments[i]`
+        args: Option<Vec<ScriptArg>>,
+        /// Optional: run as an async script that calls `arguments[arguments.length - 1]` when done
+        #[serde(rename = "async")]
+        is_async: Option<bool>,
+        /// Optional variable name to store the JSON result under
+        store_as: Option<String>,
+    },
+
+    /// Store a value in a variable
+    #[serde(rename = "set_var")]
+    SetVar {
+        /// Variable name
+        name: String,
+        /// Value to store; strings may use placeholders
+        value: serde_json::Value,
+    },
+
+    /// Add a cookie for the current page's domain
+    #[serde(rename = "set_cookie")]
+    SetCookie {
+        /// The cookie, in the same format as the cookie file
+        #[serde(flatten)]
+        cookie: Cookie,
+    },
+
+    /// Delete a cookie by name
+    #[serde(rename = "delete_cookie")]
+    DeleteCookie {
+        /// Cookie name
+        name: String,
+    },
+
+    /// Delete all cookies visible to the current page
+    #[serde(rename = "clear_cookies")]
+    ClearCookies,
+
+    /// Save the cookies visible to the current page to a JSON cookie file
+    #[serde(rename = "save_cookies")]
+    SaveCookies {
+        /// Output file path, relative to the instruction file
+        path: String,
+    },
+
+    /// Read or modify the page's `localStorage`
+    #[serde(rename = "local_storage")]
+    LocalStorage {
+        /// Operation: "set", "get", "remove", "clear" or "dump"
+        op: StorageOp,
+        /// Item key, for "set", "get" and "remove"
+        key: Option<String>,
+        /// Item value, for "set"; values other than strings are stored as JSON
+        value: Option<serde_json::Value>,
+        /// Optional variable name to store the result of "get" or "dump" under
+        store_as: Option<String>,
+        /// Optional file to write the result of "get" or "dump" to as JSON, relative to the
+        /// instruction file
+        path: Option<String>,
+    },
+
+    /// Read or modify the page's `sessionStorage`
+    #[serde(rename = "session_storage")]
+    SessionStorage {
+        /// Operation: "set", "get", "remove", "clear" or "dump"
+        op: StorageOp,
+        /// Item key, for "set", "get" and "remove"
+        key: Option<String>,
+        /// Item value, for "set"; values other than strings are stored as JSON
+        value: Option<serde_json::Value>,
+        /// Optional variable name to store the result of "get" or "dump" under
+        store_as: Option<String>,
+        /// Optional file to write the result of "get" or "dump" to as JSON, relative to the
+        /// instruction file
+        path: Option<String>,
+    },
+
+    /// Read a value from one or all matching elements into a variable and the output file
+    #[serde(rename = "extract")]
+    Extract {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional: read this attribute instead of the text
+        attribute: Option<String>,
+        /// Optional: read this DOM property instead of the text
+        property: Option<String>,
+        /// Optional: read the inner HTML instead of the text
+        html: Option<bool>,
+        /// Optional regex; the first capture group, or the whole match, is kept
+        pattern: Option<String>,
+        /// Optional: read every matching element into a list instead of the first one
+        all: Option<bool>,
+        /// Optional: store null instead of failing when no element matches
+        optional: Option<bool>,
+        /// Variable name to store the value under, also used as the name in the output file
+        store_as: String,
+    },
+
+    /// Save a table, or a grid of row and cell elements, to a CSV or JSON file
+    #[serde(rename = "extract_table")]
+    ExtractTable {
+        /// Selector of the `<table>` or grid container
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Optional CSS selector of the rows within the container, for grids not built from `<tr>`
+        row_selector: Option<String>,
+        /// Optional CSS selector of the cells within a row
+        cell_selector: Option<String>,
+        /// Optional number of header rows; by default leading `<thead>` or all-header rows
+        header_rows: Option<usize>,
+        /// Optional output format: "csv" (default) or "json"
+        format: Option<TableFormat>,
+        /// Optional output file, relative to the output directory or else the instruction file
+        path: Option<String>,
+        /// Optional variable name to store the rows under, as a list of objects
+        store_as: Option<String>,
+    },
+
+    /// Assert that an element's text contains (or equals) a value
+    #[serde(rename = "assert_text")]
+    AssertText {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Expected text
+        text: String,
+        /// Optional: require the trimmed text to match exactly instead of containing it
+        exact: Option<bool>,
+    },
+
+    /// Assert that the current URL matches a regular expression
+    #[serde(rename = "assert_url")]
+    AssertUrl {
+        /// Regular expression the URL must match
+        pattern: String,
+    },
+
+    /// Assert that the page title contains (or equals) a value
+    #[serde(rename = "assert_title")]
+    AssertTitle {
+        /// Expected title
+        title: String,
+        /// Optional: require the title to match exactly instead of containing it
+        exact: Option<bool>,
+    },
+
+    /// Assert the number of elements matching a selector
+    #[serde(rename = "assert_element_count")]
+    AssertElementCount {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Optional exact number of elements
+        count: Option<usize>,
+        /// Optional minimum number of elements
+        min: Option<usize>,
+        /// Optional maximum number of elements
+        max: Option<usize>,
+    },
+
+    /// Assert the value of an element attribute
+    #[serde(rename = "assert_attribute")]
+    AssertAttribute {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter first
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Timeout in seconds
+        timeout: Option<f64>,
+        /// Attribute name
+        attribute: String,
+        /// Expected attribute value
+        value: String,
+    },
+
+    /// Run one list of instructions or another depending on a condition
+    #[serde(rename = "if")]
+    If {
+        /// Condition to evaluate once, when the block is reached
+        condition: Condition,
+        /// Instructions to run when the condition holds
+        then: Vec<Instruction>,
+        /// Optional instructions to run when it does not
+        #[serde(rename = "else")]
+        otherwise: Option<Vec<Instruction>>,
+    },
+
+    /// Run a list of instructions a fixed number of times
+    #[serde(rename = "repeat")]
+    Repeat {
+        /// Number of iterations
+        times: u32,
+        /// Instructions to run in each iteration
+        steps: Vec<Instruction>,
+    },
+
+    /// Run a list of instructions for as long as a condition holds
+    #[serde(rename = "while")]
+    While {
+        /// Condition checked before each iteration
+        condition: Condition,
+        /// Optional upper bound on the number of iterations (default: 100)
+        max_iterations: Option<u32>,
+        /// Instructions to run in each iteration
+        steps: Vec<Instruction>,
+    },
+
+    /// Run a list of instructions again when it fails, waiting longer between attempts
+    #[serde(rename = "retry")]
+    Retry {
+        /// Maximum number of attempts (default: 3)
+        attempts: Option<u32>,
+        /// Delay before the second attempt in seconds (default: 1)
+        delay: Option<f64>,
+        /// Optional standard deviation for delay
+        delay_stdev: Option<f64>,
+        /// How the delay grows between attempts (default: "exponential")
+        backoff: Option<Backoff>,
+        /// Optional error kinds worth another attempt (default: any error)
+        retry_on: Option<Vec<ErrorKind>>,
+        /// Instructions to run in each attempt
+        steps: Vec<Instruction>,
+    },
+
+    /// Run a list of instructions, handling its failure and always cleaning up
+    #[serde(rename = "try")]
+    Try {
+        /// Instructions to run
+        steps: Vec<Instruction>,
+        /// Optional instructions to run when a step fails; the error is available as {{error}}
+        catch: Option<Vec<Instruction>>,
+        /// Optional instructions to run afterwards, whether the steps failed or not
+        finally: Option<Vec<Instruction>>,
+    },
+
+    /// Run another instruction file as a subroutine
+    #[serde(rename = "call")]
+    Call {
+        /// Path to the instruction file, relative to the calling file
+        file: String,
+        /// Optional parameters, available to the called file as variables
+        params: Option<HashMap<String, serde_json::Value>>,
+        /// Optional variable name to store the value passed to `return` under
+        store_as: Option<String>,
+    },
+
+    /// Stop the current instruction file, handing a value back to the caller
+    #[serde(rename = "return")]
+    Return {
+        /// Optional value to return
+        value: Option<serde_json::Value>,
+    },
+
+    /// Run a list of instructions once for every element matching a selector
+    #[serde(rename = "for_each")]
+    ForEach {
+        /// Element selector to find multiple matches
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to enter, and stay in for the steps
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Optional maximum number of elements to visit
+        limit: Option<usize>,
+        /// Optional: visit the elements in random order
+        shuffle: Option<bool>,
+        /// Optional list of text substrings to exclude
+        exclude_text: Option<Vec<String>>,
+        /// Instructions to run for each element; their selectors resolve relative to it
+        steps: Vec<Instruction>,
+    },
+}
+
+impl Instruction {
+    /// Whether this instruction is a control-flow block that runs nested instructions
+    pub fn is_block(&self) -> bool {
+        matches!(
+            self,
+            Instruction::If { .. }
+                | Instruction::Repeat { .. }
+                | Instruction::While { .. }
+                | Instruction::ForEach { .. }
+                | Instruction::Retry { .. }
+                | Instruction::Try { .. }
+                | Instruction::Call { .. }
+        )
+    }
+
+    /// Instructions nested directly inside this block
+    pub fn nested(&self) -> Vec<&Instruction> {
+        match self {
+            Instruction::If {
+                then, otherwise, ..
+            } => then.iter().chain(otherwise.iter().flatten()).collect(),
+            Instruction::Repeat { steps, .. }
+            | Instruction::While { steps, .. }
+            | Instruction::ForEach { steps, .. }
+            | Instruction::Retry { steps, .. } => steps.iter().collect(),
+            Instruction::Try {
+                steps,
+                catch,
+                finally,
+            } => steps
+                .iter()
+                .chain(catch.iter().flatten())
+                .chain(finally.iter().flatten())
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Selectors of the nested iframes to enter before running this instruction
+    pub fn frame_path(&self) -> Option<&[String]> {
+        match self {
+            Instruction::Click { frame, .. }
+            | Instruction::Type { frame, .. }
+            | Instruction::Select { frame, .. }
+            | Instruction::SetChecked { frame, .. }
+            | Instruction::Hover { frame, .. }
+            | Instruction::DoubleClick { frame, .. }
+            | Instruction::ContextClick { frame, .. }
+            | Instruction::ModifierClick { frame, .. }
+            | Instruction::DragTo { frame, .. }
+            | Instruction::Keys { frame, .. }
+            | Instruction::WaitFor { frame, .. }
+            | Instruction::Scroll { frame, .. }
+            | Instruction::ScrollTo { frame, .. }
+            | Instruction::ScrollUntil { frame, .. }
+            | Instruction::RandomClick { frame, .. }
+            | Instruction::Screenshot { frame, .. }
+            | Instruction::AssertText { frame, .. }
+            | Instruction::AssertElementCount { frame, .. }
+            | Instruction::Extract { frame, .. }
+            | Instruction::ExtractTable { frame, .. }
+            | Instruction::AssertAttribute { frame, .. }
+            | Instruction::Upload { frame, .. }
+            | Instruction::ForEach { frame, .. } => frame.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Selectors of the nested shadow hosts to search within for this instruction's elements
+    pub fn shadow_hosts(&self) -> Option<&[String]> {
+        match self {
+            Instruction::Click { shadow, .. }
+            | Instruction::Type { shadow, .. }
+            | Instruction::Select { shadow, .. }
+            | Instruction::SetChecked { shadow, .. }
+            | Instruction::Hover { shadow, .. }
+            | Instruction::DoubleClick { shadow, .. }
+            | Instruction::ContextClick { shadow, .. }
+            | Instruction::ModifierClick { shadow, .. }
+            | Instruction::DragTo { shadow, .. }
+            | Instruction::Keys { shadow, .. }
+            | Instruction::WaitFor { shadow, .. }
+            | Instruction::Scroll { shadow, .. }
+            | Instruction::ScrollTo { shadow, .. }
+            | Instruction::ScrollUntil { shadow, .. }
+            | Instruction::RandomClick { shadow, .. }
+            | Instruction::Screenshot { shadow, .. }
+            | Instruction::AssertText { shadow, .. }
+            | Instruction::AssertElementCount { shadow, .. }
+            | Instruction::Extract { shadow, .. }
+            | Instruction::ExtractTable { shadow, .. }
+            | Instruction::AssertAttribute { shadow, .. }
+            | Instruction::Upload { shadow, .. }
+            | Instruction::ForEach { shadow, .. } => shadow.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+/// Condition polled by the `wait_for` instruction
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum WaitCondition {
+    /// An element matches the selector
+    Present,
+    /// No element matches the selector
+    Absent,
+    /// The element is displayed
+    Visible,
+    /// The element is missing or not displayed
+    Hidden,
+    /// The element is enabled
+    Enabled,
+    /// The element is displayed and enabled
+    Clickable,
+    /// The element's text contains `text`
+    Text,
+    /// The element's `attribute` equals `value`
+    Attribute,
+    /// The current URL matches `pattern`
+    Url,
+    /// The page title matches `pattern`
+    Title,
+}
+
+/// Condition evaluated by control-flow blocks such as `if`
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// An element matches the selector
+    Exists {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to look in
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+    },
+    /// An element matches the selector and is displayed
+    Visible {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to look in
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
     },
+    /// The text of the first matching element matches a regular expression
+    Text {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional frame path: selectors of nested iframes to look in
+        frame: Option<Vec<String>>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+        /// Regular expression the text must match
+        pattern: String,
+    },
+    /// The current URL matches a regular expression
+    Url(String),
+    /// The page title matches a regular expression
+    Title(String),
+    /// A stored variable passes every given comparison, or is truthy if none is given
+    Var {
+        /// Variable name
+        name: String,
+        /// Optional value the variable must equal
+        equals: Option<serde_json::Value>,
+        /// Optional value the variable must not equal
+        not_equals: Option<serde_json::Value>,
+        /// Optional regular expression the variable must match
+        matches: Option<String>,
+        /// Optional number the variable must be greater than
+        greater_than: Option<f64>,
+        /// Optional number the variable must be less than
+        less_than: Option<f64>,
+    },
+    /// The nested condition does not hold
+    Not(Box<Condition>),
+    /// Every nested condition holds
+    All(Vec<Condition>),
+    /// At least one nested condition holds
+    Any(Vec<Condition>),
+}
+
+/// File format written by the `extract_table` instruction
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum TableFormat {
+    /// Comma-separated values with a header row
+    #[default]
+    Csv,
+    /// A list of objects keyed by column header
+    Json,
+}
+
+impl TableFormat {
+    /// File extension used for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            TableFormat::Csv => "csv",
+            TableFormat::Json => "json",
+        }
+    }
+}
+
+/// Operation of the `local_storage` and `session_storage` instructions
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageOp {
+    /// Store an item
+    Set,
+    /// Read an item
+    Get,
+    /// Remove an item
+    Remove,
+    /// Remove all items
+    Clear,
+    /// Read all items as an object
+    Dump,
+}
+
+/// Vertical alignment of an element scrolled into view
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ScrollBlock {
+    /// Align the element with the top of the viewport
+    Start,
+    /// Center the element in the viewport
+    #[default]
+    Center,
+    /// Align the element with the bottom of the viewport
+    End,
+    /// Scroll as little as possible
+    Nearest,
+}
+
+/// How the delay between `retry` attempts grows
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Backoff {
+    /// The same delay before every attempt
+    Constant,
+    /// The delay grows by its initial value with each attempt
+    Linear,
+    /// The delay doubles with each attempt
+    #[default]
+    Exponential,
+}
+
+/// Kind of error a `retry` block can retry on
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorKind {
+    /// Any error
+    Any,
+    /// A wait, page load or script ran out of time
+    Timeout,
+    /// No element matched a selector
+    NotFound,
+    /// An element was removed from the page after it was found
+    Stale,
+    /// Another element would have received a click
+    Intercepted,
+    /// An element cannot be clicked or typed into
+    NotInteractable,
+    /// A script threw an exception
+    Script,
+    /// A JavaScript dialog blocked an instruction
+    Dialog,
+    /// An assertion failed; such failures are recorded rather than retried unless listed
+    Assertion,
+}
+
+/// An argument passed to a custom script
+#[derive(Debug, Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub enum ScriptArg {
+    /// An element located on the page
+    Element {
+        /// Element selector
+        selector: String,
+        /// Selector type: "css", "id", or "xpath"
+        by: Option<String>,
+        /// Optional shadow host chain: selectors of nested shadow hosts to search within
+        shadow: Option<Vec<String>>,
+    },
+    /// The value of a stored variable
+    Variable {
+        /// Variable name
+        var: String,
+    },
+    /// A literal JSON value
+    Value(serde_json::Value),
+}
+
+/// Strategy used to click an element
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ClickMode {
+    /// Fire a synthetic `click()` through JavaScript
+    #[default]
+    Js,
+    /// Use the WebDriver element click command
+    Native,
+    /// Move the pointer and press the button through the Actions API
+    Actions,
 }
 
 /// Represents a browser cookie
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, Clone)]
 pub struct Cookie {
     /// Cookie name
     pub name: String,
     /// Cookie value
     pub value: String,
     /// Cookie domain
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub domain: Option<String>,
     /// Cookie path
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub path: Option<String>,
     /// Whether the cookie is secure
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub secure: Option<bool>,
     /// Whether the cookie is HTTP only
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub http_only: Option<bool>,
     /// Cookie expiration timestamp in seconds since epoch
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub expiry: Option<u64>,
 }
@@ -0,0 +1,256 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use serde_json::Value;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Format of the file extracted values are written to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON object per line
+    #[default]
+    JsonLines,
+    /// Comma-separated values with a header row
+    Csv,
+    /// A YAML list of records
+    Yaml,
+}
+
+impl OutputFormat {
+    /// File extension used for this format
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::JsonLines => "jsonl",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Yaml => "yaml",
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "jsonl" | "json-lines" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            _ => Err(format!("expected jsonl, csv or yaml, got {:?}", s)),
+        }
+    }
+}
+
+/// A value read by an `extract` instruction
+#[derive(Debug, Clone, Serialize)]
+pub struct Record {
+    /// URL of the link being processed
+    pub link: String,
+    /// Instruction number within the instruction file
+    pub step: usize,
+    /// Variable name the value was stored under
+    pub name: String,
+    /// The value: a string, a list of strings, or null
+    pub value: Value,
+}
+
+/// Destination for extracted values
+///
+/// Implement this to send records somewhere other than a file, and pass it to
+/// `WebAutomator::with_output_sink`.
+pub trait OutputSink: fmt::Debug + Send {
+    /// Write a single record
+    fn write(&mut self, record: &Record) -> Result<()>;
+}
+
+/// Create `extracted.<ext>` in `dir` and return a sink writing to it in `format`
+pub fn open(dir: &Path, format: OutputFormat) -> Result<Box<dyn OutputSink>> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create directory: {}", dir.display()))?;
+    let path = dir.join(format!("extracted.{}", format.extension()));
+    let file = FileSink::create(path)?;
+
+    Ok(match format {
+        OutputFormat::JsonLines => Box::new(JsonLinesSink(file)),
+        OutputFormat::Csv => Box::new(CsvSink::new(file)?),
+        OutputFormat::Yaml => Box::new(YamlSink(file)),
+    })
+}
+
+/// Output file shared by the built-in sinks; every record is written straight through
+#[derive(Debug)]
+struct FileSink {
+    path: PathBuf,
+    file: File,
+}
+
+impl FileSink {
+    fn create(path: PathBuf) -> Result<Self> {
+        let file = File::create(&path)
+            .with_context(|| format!("Failed to create output file: {}", path.display()))?;
+        Ok(Self { path, file })
+    }
+
+    fn append(&mut self, text: &str) -> Result<()> {
+        self.file
+            .write_all(text.as_bytes())
+            .with_context(|| format!("Failed to write output file: {}", self.path.display()))
+    }
+}
+
+/// Writes each record as a JSON object on its own line
+#[derive(Debug)]
+struct JsonLinesSink(FileSink);
+
+impl OutputSink for JsonLinesSink {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let line = serde_json::to_string(record)?;
+        self.0.append(&format!("{}\n", line))
+    }
+}
+
+/// Writes each record as a CSV row; values other than strings are written as JSON
+#[derive(Debug)]
+struct CsvSink(FileSink);
+
+impl CsvSink {
+    fn new(mut file: FileSink) -> Result<Self> {
+        file.append("link,step,name,value\n")?;
+        Ok(Self(file))
+    }
+}
+
+impl OutputSink for CsvSink {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        let value = match &record.value {
+            Value::String(s) => s.clone(),
+            Value::Null => String::new(),
+            other => other.to_string(),
+        };
+        let row = [
+            csv_field(&record.link),
+            record.step.to_string(),
+            csv_field(&record.name),
+            csv_field(&value),
+        ]
+        .join(",");
+        self.0.append(&format!("{}\n", row))
+    }
+}
+
+/// Writes records as items of a single YAML list
+#[derive(Debug)]
+struct YamlSink(FileSink);
+
+impl OutputSink for YamlSink {
+    fn write(&mut self, record: &Record) -> Result<()> {
+        // A one-item list, so consecutive records form one list
+        let item = serde_yaml::to_string(&[record])?;
+        self.0.append(&item)
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+pub fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> [Record; 2] {
+        [
+            Record {
+                link: "https://example.com/?a=1,2".to_string(),
+                step: 3,
+                name: "order".to_string(),
+                value: "say \"hi\"".into(),
+            },
+            Record {
+                link: "https://example.com/".to_string(),
+                step: 4,
+                name: "items".to_string(),
+                value: serde_json::json!(["a", null]),
+            },
+        ]
+    }
+
+    /// Write the records in `format` to a fresh directory and read the file back
+    fn write_all(format: OutputFormat) -> String {
+        let dir = std::env::temp_dir().join(format!(
+            "web_automator_sink_{}_{}",
+            format.extension(),
+            std::process::id()
+        ));
+        let mut sink = open(&dir, format).unwrap();
+        for record in &records() {
+            sink.write(record).unwrap();
+        }
+        let written =
+            fs::read_to_string(dir.join(format!("extracted.{}", format.extension()))).unwrap();
+        fs::remove_dir_all(&dir).ok();
+        written
+    }
+
+    #[test]
+    fn csv_field_quotes_only_when_needed() {
+        assert_eq!(csv_field("plain"), "plain");
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+        assert_eq!(csv_field("cr\r"), "\"cr\r\"");
+    }
+
+    #[test]
+    fn json_lines_sink_writes_one_object_per_line() {
+        let written = write_all(OutputFormat::JsonLines);
+        let lines: Vec<Value> = written
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            serde_json::json!({
+                "link": "https://example.com/?a=1,2",
+                "step": 3,
+                "name": "order",
+                "value": "say \"hi\""
+            })
+        );
+        assert_eq!(lines[1]["value"], serde_json::json!(["a", null]));
+    }
+
+    #[test]
+    fn csv_sink_writes_header_and_quoted_rows() {
+        assert_eq!(
+            write_all(OutputFormat::Csv),
+            "link,step,name,value\n\
+             \"https://example.com/?a=1,2\",3,order,\"say \"\"hi\"\"\"\n\
+             https://example.com/,4,items,\"[\"\"a\"\",null]\"\n"
+        );
+    }
+
+    #[test]
+    fn yaml_sink_writes_a_single_list() {
+        let written: Vec<serde_yaml::Value> =
+            serde_yaml::from_str(&write_all(OutputFormat::Yaml)).unwrap();
+        assert_eq!(written.len(), 2);
+        assert_eq!(written[0]["name"], "order");
+        assert_eq!(written[1]["step"], 4);
+    }
+
+    #[test]
+    fn output_format_parses_names() {
+        assert_eq!("JSONL".parse(), Ok(OutputFormat::JsonLines));
+        assert_eq!("yml".parse(), Ok(OutputFormat::Yaml));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,88 @@
+use anyhow::{Context, Result};
+use fantoccini::Client;
+use serde_json::Value;
+
+use crate::models::StorageOp;
+
+/// Web storage area of the current page
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageArea {
+    /// `window.localStorage`, kept across sessions
+    Local,
+    /// `window.sessionStorage`, cleared when the tab is closed
+    Session,
+}
+
+impl StorageArea {
+    /// Name of the area's property on `window`
+    pub fn name(self) -> &'static str {
+        match self {
+            StorageArea::Local => "localStorage",
+            StorageArea::Session => "sessionStorage",
+        }
+    }
+}
+
+/// Apply a storage operation to the current page
+///
+/// Returns the item for `Get` (`null` when missing), an object of all items for `Dump`, and
+/// `null` otherwise. Values other than strings are stored as JSON.
+pub async fn apply(
+    client: &Client,
+    area: StorageArea,
+    op: StorageOp,
+    key: Option<&str>,
+    value: Option<&Value>,
+) -> Result<Value> {
+    let (script, args) = match op {
+        StorageOp::Set => {
+            let key = require_key(area, op, key)?;
+            let value = match value {
+                Some(Value::String(s)) => s.clone(),
+                Some(other) => other.to_string(),
+                None => return Err(anyhow::anyhow!("{} set requires a 'value'", area.name())),
+            };
+            (
+                "window[arguments[0]].setItem(arguments[1], arguments[2]); return null;",
+                vec![Value::from(key), Value::from(value)],
+            )
+        }
+        StorageOp::Get => (
+            "return window[arguments[0]].getItem(arguments[1]);",
+            vec![Value::from(require_key(area, op, key)?)],
+        ),
+        StorageOp::Remove => (
+            "window[arguments[0]].removeItem(arguments[1]); return null;",
+            vec![Value::from(require_key(area, op, key)?)],
+        ),
+        StorageOp::Clear => ("window[arguments[0]].clear(); return null;", vec![]),
+        StorageOp::Dump => (
+            "const storage = window[arguments[0]];
+             const items = {};
+             for (let i = 0; i < storage.length; i++) {
+                 const key = storage.key(i);
+                 items[key] = storage.getItem(key);
+             }
+             return items;",
+            vec![],
+        ),
+    };
+
+    let mut script_args = vec![Value::from(area.name())];
+    script_args.extend(args);
+
+    client
+        .execute(script, script_args)
+        .await
+        .with_context(|| format!("Failed to access {}", area.name()))
+}
+
+fn require_key(area: StorageArea, op: StorageOp, key: Option<&str>) -> Result<String> {
+    key.map(str::to_string).ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} {} requires a 'key'",
+            area.name(),
+            format!("{:?}", op).to_lowercase()
+        )
+    })
+}
@@ -0,0 +1,205 @@
+use anyhow::{Context, Result};
+use fantoccini::wd::WindowHandle;
+use fantoccini::Client;
+use log::{info, warn};
+use regex::Regex;
+use std::time::Duration;
+use tokio::time::{sleep, Instant};
+
+/// Switch to the window at `index`, or the first window whose title and URL match
+pub async fn switch_to_matching(
+    client: &Client,
+    index: Option<usize>,
+    title: Option<&Regex>,
+    url: Option<&Regex>,
+) -> Result<()> {
+    let windows = client.windows().await.context("Failed to list windows")?;
+
+    if let Some(index) = index {
+        let window = windows.get(index).cloned().with_context(|| {
+            format!(
+                "No window at index {} ({} windows open)",
+                index,
+                windows.len()
+            )
+        })?;
+        return client
+            .switch_to_window(window)
+            .await
+            .context("Failed to switch window");
+    }
+
+    let original = client.window().await.ok();
+    for window in windows {
+        client
+            .switch_to_window(window)
+            .await
+            .context("Failed to switch window")?;
+
+        let title_matches = match title {
+            Some(re) => re.is_match(&client.title().await?),
+            None => true,
+        };
+        let url_matches = match url {
+            Some(re) => re.is_match(client.current_url().await?.as_str()),
+            None => true,
+        };
+        if title_matches && url_matches {
+            return Ok(());
+        }
+    }
+
+    // Nothing matched, so go back to where we started
+    if let Some(original) = original {
+        client.switch_to_window(original).await?;
+    }
+
+    Err(anyhow::anyhow!(
+        "No window matches title /{}/ and URL /{}/",
+        title.map_or(".*", |re| re.as_str()),
+        url.map_or(".*", |re| re.as_str())
+    ))
+}
+
+/// Close the current window and switch to the first remaining one
+pub async fn close_current(client: &Client) -> Result<()> {
+    client
+        .close_window()
+        .await
+        .context("Failed to close window")?;
+
+    let windows = client.windows().await.context("Failed to list windows")?;
+    match windows.into_iter().next() {
+        Some(window) => client
+            .switch_to_window(window)
+            .await
+            .context("Failed to switch window"),
+        None => {
+            warn!("Closed the last open window");
+            Ok(())
+        }
+    }
+}
+
+/// Wait until a window that is not in `known` appears, then switch to it
+pub async fn wait_for_new_window(
+    client: &Client,
+    known: &[WindowHandle],
+    timeout: Duration,
+) -> Result<()> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        if follow_new_window(client, known).await? {
+            return Ok(());
+        }
+        if Instant::now() >= deadline {
+            return Err(anyhow::anyhow!(
+                "Timed out after {:?} waiting for a new window",
+                timeout
+            ));
+        }
+        sleep(Duration::from_millis(250)).await;
+    }
+}
+
+/// Switch to the newest window not in `known`, returning whether one was found
+pub async fn follow_new_window(client: &Client, known: &[WindowHandle]) -> Result<bool> {
+    let windows = client.windows().await.context("Failed to list windows")?;
+
+    match windows.into_iter().rev().find(|w| !known.contains(w)) {
+        Some(window) => {
+            info!("Switching to new window");
+            client
+                .switch_to_window(window)
+                .await
+                .context("Failed to switch to new window")?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Move and resize the current window, keeping whatever is not given
+///
+/// With `viewport` the size applies to the page area, excluding the browser's own toolbars.
+/// The current rect is only read when part of the position or size is missing, since reading
+/// it fails for windows at negative coordinates.
+pub async fn set_rect(
+    client: &Client,
+    position: (Option<u32>, Option<u32>),
+    size: (Option<u32>, Option<u32>),
+    viewport: bool,
+) -> Result<()> {
+    let partial = |pair: (Option<u32>, Option<u32>)| pair.0.is_some() != pair.1.is_some();
+    let current = if partial(position) || partial(size) {
+        let (x, y, width, height) = client
+            .get_window_rect()
+            .await
+            .context("Failed to read window position and size")?;
+        Some([x, y, width, height].map(|value| u32::try_from(value).ok()))
+    } else {
+        None
+    };
+    let keep = |given: Option<u32>, index: usize, name: &str| -> Result<u32> {
+        given
+            .or_else(|| current.and_then(|current| current[index]))
+            .with_context(|| format!("Current window {} is out of range", name))
+    };
+
+    let position = match position {
+        (None, None) => None,
+        (x, y) => Some((keep(x, 0, "x")?, keep(y, 1, "y")?)),
+    };
+    let size = match size {
+        (None, None) => None,
+        (width, height) => {
+            let mut width = keep(width, 2, "width")?;
+            let mut height = keep(height, 3, "height")?;
+
+            if viewport {
+                // Space taken by the browser chrome around the viewport
+                let chrome = client
+                    .execute(
+                        "return [window.outerWidth - window.innerWidth, window.outerHeight - window.innerHeight];",
+                        vec![],
+                    )
+                    .await
+                    .context("Failed to measure browser chrome")?;
+                let extra = |index: usize| {
+                    chrome[index]
+                        .as_u64()
+                        .and_then(|extra| u32::try_from(extra).ok())
+                        .unwrap_or(0)
+                };
+                if size.0.is_some() {
+                    width = width.saturating_add(extra(0));
+                }
+                if size.1.is_some() {
+                    height = height.saturating_add(extra(1));
+                }
+            }
+
+            Some((width, height))
+        }
+    };
+
+    match (position, size) {
+        (Some((x, y)), Some((width, height))) => {
+            info!("Setting window to {}x{} at ({}, {})", width, height, x, y);
+            client.set_window_rect(x, y, width, height).await
+        }
+        (Some((x, y)), None) => {
+            info!("Moving window to ({}, {})", x, y);
+            client.set_window_position(x, y).await
+        }
+        (None, Some((width, height))) => {
+            info!("Resizing window to {}x{}", width, height);
+            client.set_window_size(width, height).await
+        }
+        (None, None) => {
+            warn!("set_window without a position, size or maximize does nothing");
+            return Ok(());
+        }
+    }
+    .context("Failed to set window position and size")
+}